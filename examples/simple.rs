@@ -5,9 +5,8 @@ use rsg::camera::*;
 use rsg::material::*;
 use rsg::mesh::*;
 use nalgebra_glm as glm;
-use smallvec::*;
 
-type Scene = RSGScene::<RSGComponentLinks, RSGSceneObserver>;
+type Scene = RSGScene::<RSGComponentLinks, RSGObserverList>;
 type MeshBuffers = std::collections::HashMap<u32, RSGMeshBuffer>;
 type ShaderSets = std::collections::HashMap<u32, RSGMaterialShaderSet>;
 
@@ -36,17 +35,16 @@ fn make_color_material(shader_sets: &mut ShaderSets) -> RSGMaterial {
     let mvp_name = "mvp".to_owned();
     let color_name = "color".to_owned();
 
-    if !shader_sets.contains_key(&COLOR_SH_ID) {
-        let shader_set = RSGMaterialShaderSet {
+    shader_sets.entry(COLOR_SH_ID).or_insert_with(|| {
+        RSGMaterialShaderSet {
             vertex_shader: "".to_owned(),
             fragment_shader: "".to_owned(),
             properties: vec![
                 RSGMaterialProperty::Mat4(mvp_name.clone(), glm::one()),
                 RSGMaterialProperty::Vec3(color_name.clone(), glm::zero())
             ]
-        };
-        shader_sets.insert(COLOR_SH_ID, shader_set);
-    }
+        }
+    });
 
     let mut material = RSGMaterial {
         shader_set_id: COLOR_SH_ID,
@@ -61,17 +59,16 @@ fn make_color_material(shader_sets: &mut ShaderSets) -> RSGMaterial {
 fn make_triangle(components: &mut RSGComponentContainer, buffers: &mut MeshBuffers, shader_sets: &mut ShaderSets,
     local_transform: glm::Mat4, opacity: f32) -> RSGNode<RSGComponentLinks>
 {
-    if !buffers.contains_key(&TRIANGLE_BUF_ID) {
-        let buf = RSGMeshBuffer {
+    buffers.entry(TRIANGLE_BUF_ID).or_insert_with(|| {
+        RSGMeshBuffer {
             data: vec![
                 -1.0, -1.0, 0.0,
                 1.0, -1.0, 0.0,
                 0.5, 1.0, 0.0
             ],
             source: Default::default()
-        };
-        buffers.insert(TRIANGLE_BUF_ID, buf);
-    }
+        }
+    });
 
     let mesh = RSGMesh {
         vertex_views: smallvec::smallvec![RSGMeshBufferView {
@@ -109,11 +106,11 @@ struct Data {
     components: RSGComponentContainer,
     mesh_buffers: MeshBuffers,
     shader_sets: ShaderSets,
-    opaque_list: RSGRenderList,
-    alpha_list: RSGRenderList,
+    render_lists: RSGViewportRenderLists,
     work_list: Vec<RSGNodeKey>,
     root_key: RSGNodeKey,
-    frame_count: u32
+    frame_count: u32,
+    observer: std::sync::Arc<std::sync::Mutex<RSGSceneObserver>>
 }
 
 fn sync(d: &mut Data, scene: &mut Scene) {
@@ -127,7 +124,9 @@ fn sync(d: &mut Data, scene: &mut Scene) {
                 near: 0.01,
                 far: 1000.0
             })));
+        scene.set_name(cam_key, "camera");
         let vp_key = scene.append(d.root_key, make_viewport(&mut d.components, cam_key));
+        scene.set_name(vp_key, "viewport");
 
         let mut transaction = RSGSubtreeAddTransaction::new();
         let tri1_key = scene.append_with_transaction(vp_key, make_triangle(&mut d.components, &mut d.mesh_buffers, &mut d.shader_sets,
@@ -137,32 +136,34 @@ fn sync(d: &mut Data, scene: &mut Scene) {
             glm::translation(&glm::vec3(0.3, 0.3, -2.0)), 1.0),
             &mut transaction);
         scene.commit(transaction);
+        scene.set_name(tri1_key, "triangle1");
     }
 }
 
-fn prepare(d: &mut Data, scene: &Scene, observer: &RSGSceneObserver, pool: &scoped_pool::Pool) {
+fn prepare(d: &mut Data, scene: &Scene, observer: &mut RSGSceneObserver, pool: &scoped_pool::Pool) {
     println!("Frame {} prepare, changes={:?}", d.frame_count, observer);
     if observer.changed {
-        prepare_scene(&mut d.components, &scene,
-            &observer.dirty_world_roots, &observer.dirty_opacity_roots,
-            &mut d.opaque_list, &mut d.alpha_list, &mut d.work_list,
-            &pool);
-        d.components.print_scene(&scene, d.root_key, Some(10));
+        prepare_scene(&mut d.components, scene, observer, &mut d.render_lists, &mut d.work_list, pool);
+        d.components.print_scene(scene, d.root_key, Some(10));
     }
 }
 
 fn render(d: &mut Data, _scene: &Scene) {
     println!("Frame {} render", d.frame_count);
-    println!("  Opaque list={:?}", d.opaque_list);
-    println!("  Alpha list={:?}", d.alpha_list);
+    for render_list in &d.render_lists {
+        println!("  Viewport {:?} rect={:?} camera={:?} {:?}",
+            render_list.viewport_node_key, render_list.rect, render_list.camera_node_key, render_list.culling_stats());
+        println!("    Opaque list={:?}", render_list.opaque_list);
+        println!("    Alpha list={:?}", render_list.alpha_list);
+    }
 }
 
 fn frame(d: &mut Data, scene: &mut Scene, pool: &scoped_pool::Pool) {
-    let mut observer = RSGSceneObserver::new();
-    scene.set_observer(observer);
     sync(d, scene);
-    observer = scene.take_observer().unwrap();
-    prepare(d, scene, &observer, pool);
+    let observer = d.observer.clone();
+    let mut observer = observer.lock().unwrap();
+    prepare(d, scene, &mut observer, pool);
+    observer.reset();
     render(d, scene);
     d.frame_count += 1;
 }
@@ -171,6 +172,8 @@ fn main() {
     let pool = scoped_pool::Pool::new(4);
     let mut scene = Scene::new();
     let mut d: Data = Default::default();
+    scene.set_observer(RSGObserverList::new());
+    scene.observer_mut().unwrap().subscribe(Box::new(d.observer.clone()));
     d.root_key = d.components.add_default_root(&mut scene);
 
     frame(&mut d, &mut scene, &pool);
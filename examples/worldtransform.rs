@@ -12,7 +12,8 @@ struct Data {
     node1_key: Option<RSGNodeKey>,
     node11_key: Option<RSGNodeKey>,
     node111_key: Option<RSGNodeKey>,
-    node112_key: Option<RSGNodeKey>
+    node112_key: Option<RSGNodeKey>,
+    comb_leaf_key: Option<RSGNodeKey>
 }
 
 fn sync_scene(d: &mut Data, scene: &mut Scene, stage: i32) {
@@ -83,6 +84,29 @@ fn sync_scene(d: &mut Data, scene: &mut Scene, stage: i32) {
             }
             scene.commit(t);
         },
+        9 => {
+            // Add a 50000 deep chain of group nodes without components, each with a leaf
+            // that has a transform and an opacity. Finding the nearest ancestor with a
+            // component from each leaf would make this quadratic; the update carries the
+            // parent values down the traversal instead so it stays linear.
+            let mut k = d.root_key.unwrap();
+            let mut t = RSGSubtreeAddTransaction::new();
+            for _ in 0..50000 {
+                k = scene.append_with_transaction(k, RSGNode::new(), &mut t);
+                d.comb_leaf_key = Some(scene.append_with_transaction(k, RSGNode::with_component_links(
+                    RSGComponentBuilder::new(&mut d.components).transform(glm::one()).opacity(0.5).links()),
+                    &mut t));
+            }
+            scene.commit(t);
+        },
+        10 => {
+            // Change the transform and opacity on ROOT, which dirties the entire scene.
+            // The deepest leaf of the chain follows with world pos (1, 2) and opacity 0.25.
+            let root_links = scene.get_component_links(d.root_key.unwrap());
+            d.components.transforms[root_links.transform_key.unwrap()].local_transform = glm::translation(&glm::vec3(1.0, 2.0, 0.0));
+            d.components.opacities[root_links.opacity_key.unwrap()].opacity = 0.5;
+            scene.mark_dirty(d.root_key.unwrap(), RSGDirtyFlags::TRANSFORM | RSGDirtyFlags::OPACITY);
+        },
         _ => {}
     }
 }
@@ -91,12 +115,11 @@ fn main() {
     let mut scene = Scene::new();
     let mut obs = RSGSceneObserver::new();
     let mut d = Default::default();
-    let mut opaque_list = vec![];
-    let mut alpha_list = vec![];
+    let mut render_lists = vec![];
     let mut work_list = vec![];
     let pool = scoped_pool::Pool::new(4);
 
-    for stage in 0..9 {
+    for stage in 0..11 {
         println!("Simulation step {}", stage);
         scene.set_observer(obs);
 
@@ -105,16 +128,27 @@ fn main() {
         println!("  scene synchronization took {} microseconds", timestamp.elapsed().as_micros());
 
         obs = scene.take_observer().unwrap();
+        obs.normalize(&scene);
         if obs.changed {
             println!("  total node count is {}", scene.node_count());
             println!("  roots for subtrees with dirty world transform: {:?}", obs.dirty_world_roots);
             println!("  roots for subtrees with dirty inherited opacity: {:?}", obs.dirty_opacity_roots);
             let timestamp = std::time::Instant::now();
-            prepare_scene(&mut d.components, &scene, &obs.dirty_world_roots, &obs.dirty_opacity_roots,
-                &mut opaque_list, &mut alpha_list, &mut work_list, &pool);
-            println!("  inherited property update took {} microseconds", timestamp.elapsed().as_micros());
+            prepare_scene(&mut d.components, &scene, &mut obs, &mut render_lists, &mut work_list, &pool);
+            let elapsed = timestamp.elapsed();
+            println!("  inherited property update took {} microseconds", elapsed.as_micros());
+            if stage >= 9 {
+                // stays flat for the deep chain as long as the update is linear in the node count
+                println!("  that is {} nanoseconds per node", elapsed.as_nanos() / scene.node_count() as u128);
+            }
             obs.reset();
             d.components.print_scene(&scene, d.root_key.unwrap(), Some(5));
+            if stage == 10 {
+                let leaf_links = scene.get_component_links(d.comb_leaf_key.unwrap());
+                let world_transform = d.components.transforms[leaf_links.transform_key.unwrap()].world_transform;
+                assert!(world_transform == glm::translation(&glm::vec3(1.0, 2.0, 0.0)));
+                assert!(d.components.opacities[leaf_links.opacity_key.unwrap()].inherited_opacity == 0.25);
+            }
         } else {
             println!("  no changes");
         }
@@ -1,3 +1,4 @@
+use crate::mesh::RSGAabb;
 use nalgebra_glm as glm;
 
 slotmap::new_key_type! {
@@ -32,22 +33,184 @@ pub struct RSGCameraWorldTransformDerivedProperties {
     pub direction: glm::Vec3
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RSGFrustum {
+    // (a, b, c, d) with a*x + b*y + c*z + d >= 0 for points inside, in world space,
+    // in the order left, right, bottom, top, near, far
+    pub planes: [glm::Vec4; 6]
+}
+
+impl RSGFrustum {
+    pub fn new(camera: &RSGCamera, world_transform: &glm::Mat4) -> Self {
+        // The camera looks towards -Z in its own space. The perspective fov is
+        // the vertical field of view in degrees, xmag and ymag are the half
+        // width and height of the orthographic view volume.
+        let view_planes = match camera {
+            RSGCamera::Perspective(p) => {
+                let t = (p.fov.to_radians() * 0.5).tan();
+                let at = p.aspect_ratio * t;
+                [glm::vec4(1.0, 0.0, -at, 0.0),
+                 glm::vec4(-1.0, 0.0, -at, 0.0),
+                 glm::vec4(0.0, 1.0, -t, 0.0),
+                 glm::vec4(0.0, -1.0, -t, 0.0),
+                 glm::vec4(0.0, 0.0, -1.0, -p.near),
+                 glm::vec4(0.0, 0.0, 1.0, p.far)]
+            },
+            RSGCamera::Orthographic(o) => {
+                [glm::vec4(1.0, 0.0, 0.0, o.xmag),
+                 glm::vec4(-1.0, 0.0, 0.0, o.xmag),
+                 glm::vec4(0.0, 1.0, 0.0, o.ymag),
+                 glm::vec4(0.0, -1.0, 0.0, o.ymag),
+                 glm::vec4(0.0, 0.0, -1.0, -o.near),
+                 glm::vec4(0.0, 0.0, 1.0, o.far)]
+            }
+        };
+        // a plane p in view space is transpose(view) * p in world space
+        let view_transposed = glm::transpose(&glm::inverse(world_transform));
+        let mut planes = view_planes;
+        for plane in planes.iter_mut() {
+            *plane = view_transposed * *plane;
+        }
+        RSGFrustum { planes }
+    }
+
+    pub fn intersects_aabb(&self, aabb: &RSGAabb) -> bool {
+        // conservative: false only when the box is fully outside one of the planes
+        self.planes.iter().all(|plane| {
+            let farthest_inside = glm::vec3(
+                if plane.x >= 0.0 { aabb.maximum.x } else { aabb.minimum.x },
+                if plane.y >= 0.0 { aabb.maximum.y } else { aabb.minimum.y },
+                if plane.z >= 0.0 { aabb.maximum.z } else { aabb.minimum.z });
+            glm::dot(&plane.xyz(), &farthest_inside) + plane.w >= 0.0
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RSGClipSpaceDepth {
+    MinusOneToOne,
+    ZeroToOne
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RSGClipSpace {
+    // The projection matrices are right-handed, mapping the view volume
+    // (looking towards -Z) to x and y in -1..1 with +Y up, unless y_flip is
+    // set, and to z in the depth range with near mapping to the lower end.
+    pub depth: RSGClipSpaceDepth,
+    pub y_flip: bool
+}
+
+impl RSGClipSpace {
+    pub fn opengl() -> Self {
+        RSGClipSpace { depth: RSGClipSpaceDepth::MinusOneToOne, y_flip: false }
+    }
+
+    pub fn direct3d() -> Self {
+        RSGClipSpace { depth: RSGClipSpaceDepth::ZeroToOne, y_flip: false }
+    }
+
+    pub fn vulkan() -> Self {
+        RSGClipSpace { depth: RSGClipSpaceDepth::ZeroToOne, y_flip: true }
+    }
+}
+
+impl Default for RSGClipSpace {
+    fn default() -> Self {
+        RSGClipSpace::opengl()
+    }
+}
+
+impl RSGCamera {
+    pub fn projection_matrix(&self, clip_space: RSGClipSpace) -> glm::Mat4 {
+        let projection = match (self, clip_space.depth) {
+            (RSGCamera::Perspective(p), RSGClipSpaceDepth::MinusOneToOne) =>
+                glm::perspective_rh_no(p.aspect_ratio, p.fov.to_radians(), p.near, p.far),
+            (RSGCamera::Perspective(p), RSGClipSpaceDepth::ZeroToOne) =>
+                glm::perspective_rh_zo(p.aspect_ratio, p.fov.to_radians(), p.near, p.far),
+            (RSGCamera::Orthographic(o), RSGClipSpaceDepth::MinusOneToOne) =>
+                glm::ortho_rh_no(-o.xmag, o.xmag, -o.ymag, o.ymag, o.near, o.far),
+            (RSGCamera::Orthographic(o), RSGClipSpaceDepth::ZeroToOne) =>
+                glm::ortho_rh_zo(-o.xmag, o.xmag, -o.ymag, o.ymag, o.near, o.far)
+        };
+        if clip_space.y_flip {
+            glm::scaling(&glm::vec3(1.0, -1.0, 1.0)) * projection
+        } else {
+            projection
+        }
+    }
+
+    pub fn aspect_ratio(&self) -> Option<f32> {
+        // None for an orthographic camera with a zero ymag, which has no aspect
+        // ratio that set_aspect_ratio() could change
+        match self {
+            RSGCamera::Perspective(p) => Some(p.aspect_ratio),
+            RSGCamera::Orthographic(o) => if o.ymag != 0.0 { Some(o.xmag / o.ymag) } else { None }
+        }
+    }
+
+    pub fn set_aspect_ratio(&mut self, aspect_ratio: f32) {
+        // for orthographic cameras ymag is kept and xmag follows
+        match self {
+            RSGCamera::Perspective(p) => p.aspect_ratio = aspect_ratio,
+            RSGCamera::Orthographic(o) => o.xmag = o.ymag * aspect_ratio
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RSGCameraMatrices {
+    pub projection: glm::Mat4,
+    // world to camera space, the inverse of the camera node's world transform
+    pub view: glm::Mat4,
+    pub inverse_view: glm::Mat4,
+    pub view_projection: glm::Mat4
+}
+
 #[derive(Clone, Copy)]
 pub struct RSGCameraComponent {
     pub camera: RSGCamera,
-    pub world_properties: RSGCameraWorldTransformDerivedProperties
+    pub clip_space: RSGClipSpace,
+    // when set, prepare_scene adjusts the aspect ratio to the rect of the viewport using
+    // the camera, clipped to the viewports above it (with several such viewports the
+    // first one in tree order decides, and one covering the whole target, i.e. without
+    // a rect on the way up, leaves it to the application as the target size is not known)
+    pub aspect_ratio_from_viewport: bool,
+    pub world_properties: RSGCameraWorldTransformDerivedProperties,
+    pub frustum: RSGFrustum,
+    pub matrices: RSGCameraMatrices
 }
 
 impl RSGCameraComponent {
     pub fn new(camera: RSGCamera) -> Self {
+        let clip_space = RSGClipSpace::default();
+        let projection = camera.projection_matrix(clip_space);
         RSGCameraComponent {
-            camera: camera,
+            camera,
+            clip_space,
+            aspect_ratio_from_viewport: false,
             world_properties: RSGCameraWorldTransformDerivedProperties {
                 position: glm::vec3(0.0, 0.0, 0.0),
                 direction: glm::vec3(0.0, 0.0, -1.0)
+            },
+            frustum: RSGFrustum::new(&camera, &glm::one()),
+            matrices: RSGCameraMatrices {
+                projection,
+                view: glm::one(),
+                inverse_view: glm::one(),
+                view_projection: projection
             }
         }
     }
+
+    pub fn update_matrices(&mut self, world_transform: &glm::Mat4) {
+        // to be called when the world transform, the camera, or the clip space changed
+        self.matrices.projection = self.camera.projection_matrix(self.clip_space);
+        self.matrices.inverse_view = *world_transform;
+        self.matrices.view = glm::inverse(world_transform);
+        self.matrices.view_projection = self.matrices.projection * self.matrices.view;
+        self.frustum = RSGFrustum::new(&self.camera, world_transform);
+    }
 }
 
 pub type RSGCameraComponentList = slotmap::SlotMap<RSGCameraKey, RSGCameraComponent>;
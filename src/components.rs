@@ -5,6 +5,7 @@ use crate::material::*;
 use crate::mesh::*;
 use crate::camera::*;
 use crate::viewport::*;
+use crate::observer::*;
 use nalgebra_glm as glm;
 use scoped_pool;
 
@@ -47,9 +48,11 @@ impl RSGComponentContainer {
         }
         if let Some(key) = component_links.material_key {
             self.materials.remove(key);
+            self.material_data.remove(key);
         }
         if let Some(key) = component_links.mesh_key {
             self.meshes.remove(key);
+            self.mesh_data.remove(key);
         }
         if let Some(key) = component_links.camera_key {
             self.cameras.remove(key);
@@ -59,6 +62,106 @@ impl RSGComponentContainer {
         }
     }
 
+    pub fn remove_subtree<ObserverT>(&mut self, scene: &mut RSGScene<RSGComponentLinks, ObserverT>, node_key: RSGNodeKey)
+        where ObserverT: RSGObserver
+    {
+        scene.remove_with_callback(node_key, |component_links| self.remove(component_links));
+    }
+
+    pub fn remove_detached_subtree<ObserverT>(&mut self, scene: &mut RSGScene<RSGComponentLinks, ObserverT>, detached: RSGDetachedSubtree)
+        where ObserverT: RSGObserver
+    {
+        scene.remove_detached_with_callback(detached, |component_links| self.remove(component_links));
+    }
+
+    pub fn clone_components(&mut self, component_links: &RSGComponentLinks) -> RSGComponentLinks {
+        let mut links = RSGComponentLinks::default();
+        if let Some(key) = component_links.transform_key {
+            links.transform_key = Some(self.transforms.insert(self.transforms[key]));
+        }
+        if let Some(key) = component_links.opacity_key {
+            links.opacity_key = Some(self.opacities.insert(self.opacities[key]));
+        }
+        if let Some(key) = component_links.material_key {
+            let new_key = self.materials.insert(self.materials[key]);
+            links.material_key = Some(new_key);
+            let material = self.material_data[key].clone();
+            self.material_data.insert(new_key, material);
+        }
+        if let Some(key) = component_links.mesh_key {
+            let new_key = self.meshes.insert(self.meshes[key]);
+            links.mesh_key = Some(new_key);
+            let mesh = self.mesh_data[key].clone();
+            self.mesh_data.insert(new_key, mesh);
+        }
+        if let Some(key) = component_links.camera_key {
+            links.camera_key = Some(self.cameras.insert(self.cameras[key]));
+        }
+        if let Some(key) = component_links.viewport_key {
+            links.viewport_key = Some(self.viewports.insert(self.viewports[key]));
+        }
+        links
+    }
+
+    pub fn clone_subtree<ObserverT>(&mut self, scene: &mut RSGScene<RSGComponentLinks, ObserverT>,
+        node_key: RSGNodeKey, parent_key: RSGNodeKey, position: RSGNodePosition) -> RSGNodeKey
+        where ObserverT: RSGObserver
+    {
+        // A(NODE(B), C) -> A(NODE(B), C, NODE'(B')) if parent_key == A.key and position is Append
+        // (atomic subtree add, the copy gets its own components)
+        // Notifies: add NODE'
+
+        let source_keys: Vec<RSGNodeKey> = scene.traverse(node_key).map(|(key, _)| key).collect();
+        let mut key_map: std::collections::HashMap<RSGNodeKey, RSGNodeKey> = std::collections::HashMap::new();
+        let mut transaction = RSGSubtreeAddTransaction::new();
+        for source_key in source_keys {
+            let links = self.clone_components(scene.get_component_links(source_key));
+            let node = RSGNode::with_component_links(links);
+            let new_key = if source_key == node_key {
+                scene.insert_with_transaction(parent_key, node, position, &mut transaction)
+            } else {
+                let new_parent_key = key_map[&scene[source_key].parent_key.unwrap()];
+                scene.append_with_transaction(new_parent_key, node, &mut transaction)
+            };
+            if let Some(name) = scene.name(source_key).map(|name| name.to_owned()) {
+                scene.set_name(new_key, &name);
+            }
+            key_map.insert(source_key, new_key);
+        }
+
+        // references pointing into the cloned subtree must refer to the copy
+        for new_key in key_map.values() {
+            let links = *scene.get_component_links(*new_key);
+            if let Some(viewport_key) = links.viewport_key {
+                let viewport = &mut self.viewports[viewport_key];
+                if let Some(new_camera_node_key) = viewport.camera_node_key.and_then(|key| key_map.get(&key)) {
+                    viewport.camera_node_key = Some(*new_camera_node_key);
+                }
+            }
+            if let Some(mesh_key) = links.mesh_key {
+                let mesh = &mut self.meshes[mesh_key];
+                if let Some(new_viewport_node_key) = mesh.viewport_node_key.and_then(|key| key_map.get(&key)) {
+                    mesh.viewport_node_key = Some(*new_viewport_node_key);
+                }
+            }
+        }
+
+        let new_root_key = key_map[&node_key];
+        scene.commit(transaction);
+        new_root_key
+    }
+
+    fn is_in_frustum(&self, links: &RSGComponentLinks, frustum: &RSGFrustum) -> bool {
+        let bounds = self.mesh_data[links.mesh_key.unwrap()].bounds;
+        frustum.intersects_aabb(&bounds.transformed(&self.transforms[links.transform_key.unwrap()].world_transform))
+    }
+
+    fn sorting_distance(&self, links: &RSGComponentLinks, camera_properties: &RSGCameraWorldTransformDerivedProperties) -> f32 {
+        calculate_sorting_distance(&self.transforms[links.transform_key.unwrap()].world_transform,
+            &self.mesh_data[links.mesh_key.unwrap()].bounds,
+            camera_properties)
+    }
+
     pub fn is_opaque(&self, links: &RSGComponentLinks) -> bool {
         if let Some(opacity_key) = links.opacity_key {
             if self.opacities[opacity_key].inherited_opacity < 1.0 {
@@ -70,7 +173,39 @@ impl RSGComponentContainer {
                 return false;
             }
         }
-        return true;
+        true
+    }
+
+    pub fn viewport_rect<ObserverT>(&self, scene: &RSGScene<RSGComponentLinks, ObserverT>,
+        viewport_node_key: RSGNodeKey, target_rect: RSGViewportRect) -> RSGViewportRect
+        where ObserverT: RSGObserver
+    {
+        // The rect of a nested viewport is relative to the rect of the nearest
+        // viewport above it, the outermost one is relative to target_rect.
+        // No rect means the whole area of the parent.
+        let mut viewport_keys: smallvec::SmallVec<[RSGViewportKey; 4]> = scene.ancestors_with_node(viewport_node_key)
+            .filter_map(|key| scene.get_component_links(key).viewport_key).collect();
+        viewport_keys.reverse();
+        viewport_keys.iter().fold(target_rect, |parent_rect, viewport_key| match self.viewports[*viewport_key].rect {
+            Some(rect) => rect.relative_to(&parent_rect),
+            None => parent_rect
+        })
+    }
+
+    pub fn clipped_viewport_rect<ObserverT>(&self, scene: &RSGScene<RSGComponentLinks, ObserverT>,
+        viewport_node_key: RSGNodeKey) -> Option<RSGViewportRect>
+        where ObserverT: RSGObserver
+    {
+        // Like viewport_rect() without the target: the rect clipped to the rects of
+        // the viewports above it, None when there is no rect on the way up, i.e. the
+        // viewport covers the whole target.
+        let mut viewport_keys: smallvec::SmallVec<[RSGViewportKey; 4]> = scene.ancestors_with_node(viewport_node_key)
+            .filter_map(|key| scene.get_component_links(key).viewport_key).collect();
+        viewport_keys.reverse();
+        viewport_keys.iter().fold(None, |parent_rect, viewport_key| match self.viewports[*viewport_key].rect {
+            Some(rect) => Some(parent_rect.map_or(rect, |parent_rect| rect.relative_to(&parent_rect))),
+            None => parent_rect
+        })
     }
 
     pub fn print_scene<ObserverT>(&self, scene: &RSGScene<RSGComponentLinks, ObserverT>,
@@ -85,7 +220,10 @@ impl RSGComponentContainer {
 
             let component_links = scene.get_component_links(key);
             let indent = (0..depth).map(|_| "    ").collect::<String>();
-            println!("{}----{:?} alpha={}", indent, key, !self.is_opaque(component_links));
+            match scene.name(key) {
+                Some(name) => println!("{}----{:?} \"{}\" alpha={}", indent, key, name, !self.is_opaque(component_links)),
+                None => println!("{}----{:?} alpha={}", indent, key, !self.is_opaque(component_links))
+            }
 
             if let Some(transform_key) = component_links.transform_key {
                 let t = self.transforms[transform_key];
@@ -118,8 +256,8 @@ impl RSGComponentContainer {
 
             if let Some(viewport_key) = component_links.viewport_key {
                 let v = &self.viewports[viewport_key];
-                println!("{}    viewport rect={:?} camera={:?}",
-                    indent, v.rect, v.camera_node_key);
+                println!("{}    viewport rect={:?} camera={:?} culling={}",
+                    indent, v.rect, v.camera_node_key, v.culling);
             }
         }
     }
@@ -134,7 +272,7 @@ impl<'a> RSGComponentBuilder<'a> {
     pub fn new(container: &'a mut RSGComponentContainer) -> Self {
         RSGComponentBuilder {
             links: Default::default(),
-            container: container
+            container
         }
     }
 
@@ -181,7 +319,7 @@ fn calculate_camera_world_properties(world_transform: &glm::Mat4) -> RSGCameraWo
 {
     let camera_world = world_transform;
     let camera_position = glm::vec3(camera_world[12], camera_world[13], camera_world[14]);
-    let scaling_correct_camera_world = glm::transpose(&glm::inverse(&glm::mat4_to_mat3(&camera_world)));
+    let scaling_correct_camera_world = glm::transpose(&glm::inverse(&glm::mat4_to_mat3(camera_world)));
     let camera_direction = glm::normalize(&(scaling_correct_camera_world * glm::vec3(0.0, 0.0, -1.0)));
     RSGCameraWorldTransformDerivedProperties {
         position: camera_position,
@@ -189,6 +327,20 @@ fn calculate_camera_world_properties(world_transform: &glm::Mat4) -> RSGCameraWo
     }
 }
 
+fn update_camera_world(camera: &mut RSGCameraComponent, world_transform: &glm::Mat4) {
+    camera.world_properties = calculate_camera_world_properties(world_transform);
+    camera.update_matrices(world_transform);
+}
+
+fn camera_world_transform<ObserverT>(transforms: &RSGTransformComponentList, scene: &RSGScene<RSGComponentLinks, ObserverT>,
+    camera_node_key: RSGNodeKey) -> glm::Mat4
+    where ObserverT: RSGObserver
+{
+    scene.ancestors_with_node(camera_node_key)
+        .find_map(|key| scene.get_component_links(key).transform_key)
+        .map_or_else(glm::one, |transform_key| transforms[transform_key].world_transform)
+}
+
 fn calculate_sorting_distance(world_transform: &glm::Mat4, bounds: &RSGAabb,
     camera_properties: &RSGCameraWorldTransformDerivedProperties) -> f32
 {
@@ -200,132 +352,555 @@ fn calculate_sorting_distance(world_transform: &glm::Mat4, bounds: &RSGAabb,
 fn update_inherited_opacities<ObserverT>(
     opacity_components: RSGOpacityComponentList,
     scene: &RSGScene<RSGComponentLinks, ObserverT>,
-    subtree_roots: &[RSGNodeKey]) -> RSGOpacityComponentList
+    subtree_roots: &[RSGNodeKey],
+    visibility_changes: &mut Vec<RSGNodeKey>,
+    changed_renderables: &mut Vec<RSGNodeKey>) -> RSGOpacityComponentList
     where ObserverT: RSGObserver
 {
+    // also collects the topmost nodes that became fully transparent or stopped
+    // being that, which changes the set of renderables below them, and the
+    // renderables with a changed inherited opacity, which may need to switch
+    // between the opaque and alpha lists
     let mut opacities = opacity_components;
+    // (depth, inherited opacity) for the nodes with an opacity component on the current path
+    let mut stack: Vec<(u32, f32)> = vec![];
     for subtree_root_key in subtree_roots {
-        for (key, _) in scene.traverse(*subtree_root_key) {
-            if let Some(opacity_key) = scene.get_component_links(key).opacity_key {
-                let mut inherited_opacity = opacities[opacity_key].opacity;
-                for key in scene.ancestors(key) {
-                    if let Some(opacity_key) = scene.get_component_links(key).opacity_key {
-                        inherited_opacity *= opacities[opacity_key].inherited_opacity;
-                        break;
-                    }
+        let parent_opacity = scene.ancestors(*subtree_root_key)
+            .find_map(|key| scene.get_component_links(key).opacity_key)
+            .map_or(1.0, |opacity_key| opacities[opacity_key].inherited_opacity);
+        stack.clear();
+        let mut visibility_change_depth: Option<u32> = None;
+        // Not pruned at fully transparent nodes: their descendants get 0 as well,
+        // so that is_opaque() and the renderable collection never see stale values.
+        for (key, depth) in scene.traverse(*subtree_root_key) {
+            while stack.last().is_some_and(|e| e.0 >= depth) {
+                stack.pop();
+            }
+            if visibility_change_depth.is_some_and(|d| d >= depth) {
+                visibility_change_depth = None;
+            }
+            let links = scene.get_component_links(key);
+            if let Some(opacity_key) = links.opacity_key {
+                let inherited_opacity = opacities[opacity_key].opacity * stack.last().map_or(parent_opacity, |e| e.1);
+                let old_inherited_opacity = opacities[opacity_key].inherited_opacity;
+                if (old_inherited_opacity <= 0.0) != (inherited_opacity <= 0.0) && visibility_change_depth.is_none() {
+                    visibility_changes.push(key);
+                    visibility_change_depth = Some(depth);
+                }
+                if old_inherited_opacity != inherited_opacity && links.mesh_key.is_some() {
+                    changed_renderables.push(key);
                 }
                 opacities[opacity_key].inherited_opacity = inherited_opacity;
+                stack.push((depth, inherited_opacity));
             }
         }
     }
     opacities
 }
 
+type RSGWorldTransformList = Vec<(RSGTransformKey, Option<RSGCameraKey>, glm::Mat4)>;
+
+fn calculate_world_transforms<ObserverT>(
+    transforms: &RSGTransformComponentList,
+    scene: &RSGScene<RSGComponentLinks, ObserverT>,
+    subtree_roots: &[RSGNodeKey],
+    result: &mut RSGWorldTransformList,
+    moved_renderables: &mut Vec<RSGNodeKey>)
+    where ObserverT: RSGObserver
+{
+    // (depth, world transform) for the nodes with a transform component on the current path
+    let mut stack: Vec<(u32, glm::Mat4)> = vec![];
+    for subtree_root_key in subtree_roots {
+        let parent_world_transform = scene.ancestors(*subtree_root_key)
+            .find_map(|key| scene.get_component_links(key).transform_key)
+            .map_or_else(glm::one, |transform_key| transforms[transform_key].world_transform);
+        stack.clear();
+        for (key, depth) in scene.traverse(*subtree_root_key) {
+            while stack.last().is_some_and(|e| e.0 >= depth) {
+                stack.pop();
+            }
+            let links = scene.get_component_links(key);
+            if let Some(transform_key) = links.transform_key {
+                let world_transform = transforms[transform_key].local_transform
+                    * stack.last().map_or(&parent_world_transform, |e| &e.1);
+                result.push((transform_key, links.camera_key, world_transform));
+                if links.mesh_key.is_some() {
+                    moved_renderables.push(key);
+                }
+                stack.push((depth, world_transform));
+            }
+        }
+    }
+}
+
+fn transform_to_ancestor<ObserverT>(transforms: &RSGTransformComponentList, scene: &RSGScene<RSGComponentLinks, ObserverT>,
+    node_key: RSGNodeKey, ancestor_key: Option<RSGNodeKey>) -> glm::Mat4
+    where ObserverT: RSGObserver
+{
+    // accumulates the local transforms from node_key up to, but excluding,
+    // ancestor_key (or up to the root when None), in the same order as the
+    // world transform update in prepare_scene
+    let mut m: glm::Mat4 = glm::one();
+    for key in scene.ancestors_with_node(node_key).take_while(|key| Some(*key) != ancestor_key) {
+        if let Some(transform_key) = scene.get_component_links(key).transform_key {
+            m *= transforms[transform_key].local_transform;
+        }
+    }
+    m
+}
+
+pub fn relative_transform<ObserverT>(transforms: &RSGTransformComponentList, scene: &RSGScene<RSGComponentLinks, ObserverT>,
+    from_node_key: RSGNodeKey, to_node_key: RSGNodeKey) -> Option<glm::Mat4>
+    where ObserverT: RSGObserver
+{
+    // Maps coordinates in the space of from_node_key to the space of to_node_key.
+    // Works from the local transforms so the world transforms need not be up to date.
+    // None if the nodes are not in the same tree.
+    let lca_key = scene.lowest_common_ancestor(from_node_key, to_node_key)?;
+    let lca_world = transform_to_ancestor(transforms, scene, lca_key, None);
+    let from_world = transform_to_ancestor(transforms, scene, from_node_key, Some(lca_key)) * lca_world;
+    let to_world = transform_to_ancestor(transforms, scene, to_node_key, Some(lca_key)) * lca_world;
+    Some(glm::inverse(&to_world) * from_world)
+}
+
 pub type RSGRenderList = Vec<(RSGNodeKey, f32)>;
 
+fn tree_order<ObserverT>(scene: &RSGScene<RSGComponentLinks, ObserverT>,
+    a_key: RSGNodeKey, b_key: RSGNodeKey) -> std::cmp::Ordering
+    where ObserverT: RSGObserver
+{
+    // pre-order position, decided by the children of the lowest common ancestor
+    // the two paths go through (meant for the few viewport nodes only)
+    let mut a_path: smallvec::SmallVec<[RSGNodeKey; 16]> = scene.ancestors_with_node(a_key).collect();
+    let mut b_path: smallvec::SmallVec<[RSGNodeKey; 16]> = scene.ancestors_with_node(b_key).collect();
+    a_path.reverse();
+    b_path.reverse();
+    let common = a_path.iter().zip(b_path.iter()).take_while(|(a, b)| a == b).count();
+    match (a_path.get(common), b_path.get(common)) {
+        (None, None) => std::cmp::Ordering::Equal,
+        (None, Some(_)) => std::cmp::Ordering::Less,
+        (Some(_), None) => std::cmp::Ordering::Greater,
+        (Some(a_child_key), Some(b_child_key)) => {
+            let mut key_opt = scene.next_sibling(*a_child_key);
+            while let Some(key) = key_opt {
+                if key == *b_child_key {
+                    return std::cmp::Ordering::Less;
+                }
+                key_opt = scene.next_sibling(key);
+            }
+            std::cmp::Ordering::Greater
+        }
+    }
+}
+
+fn viewport_camera<ObserverT>(scene: &RSGScene<RSGComponentLinks, ObserverT>,
+    viewport: &RSGViewportComponent) -> Option<RSGNodeKey>
+    where ObserverT: RSGObserver
+{
+    viewport.camera_node_key.filter(|key| scene.is_reachable(*key) && scene.get_component_links(*key).camera_key.is_some())
+}
+
+fn update_render_list<ObserverT>(
+    components: &RSGComponentContainer,
+    scene: &RSGScene<RSGComponentLinks, ObserverT>,
+    render_list: &mut RSGViewportRenderList,
+    camera: &RSGCameraComponent,
+    moved: &std::collections::HashSet<RSGNodeKey>,
+    reclassify: &std::collections::HashSet<RSGNodeKey>,
+    all_moved: bool)
+    where ObserverT: RSGObserver
+{
+    // The moved entries (all of them when the camera moved or the culling was
+    // switched) are culled and get their sorting distance again, the ones to reclassify move between the
+    // opaque and alpha lists when needed. Everything else is left untouched.
+    let is_moved = |key: RSGNodeKey| all_moved || moved.contains(&key);
+    let RSGViewportRenderList { opaque_list, alpha_list, culled_list, culling, .. } = render_list;
+    let frustum = if *culling { Some(&camera.frustum) } else { None };
+    let mut opaque_changed = false;
+    let mut alpha_changed = false;
+    // (key, previous sorting distance, or NaN when it was culled)
+    let mut pending: RSGRenderList = vec![];
+    for (list, opaque, changed) in [(&mut *opaque_list, true, &mut opaque_changed), (&mut *alpha_list, false, &mut alpha_changed)] {
+        list.retain(|e| {
+            let affected = is_moved(e.0)
+                || (reclassify.contains(&e.0) && components.is_opaque(scene.get_component_links(e.0)) != opaque);
+            if affected {
+                pending.push(*e);
+                *changed = true;
+            }
+            !affected
+        });
+    }
+    // without culling everything goes back to the lists
+    culled_list.retain(|key| (frustum.is_some() && !is_moved(*key)) || { pending.push((*key, f32::NAN)); false });
+    for (key, sort_dist) in pending {
+        let links = scene.get_component_links(key);
+        let moved = is_moved(key) || sort_dist.is_nan();
+        if moved && frustum.is_some_and(|frustum| !components.is_in_frustum(links, frustum)) {
+            culled_list.push(key);
+            continue;
+        }
+        let sort_dist = if moved { components.sorting_distance(links, &camera.world_properties) } else { sort_dist };
+        if components.is_opaque(links) {
+            opaque_list.push((key, sort_dist));
+            opaque_changed = true;
+        } else {
+            alpha_list.push((key, sort_dist));
+            alpha_changed = true;
+        }
+    }
+    if opaque_changed {
+        sort_render_list(opaque_list, true);
+    }
+    if alpha_changed {
+        sort_render_list(alpha_list, false);
+    }
+}
+
+fn sort_render_list(list: &mut RSGRenderList, front_to_back: bool) {
+    // Insertion sort since the order rarely changes much from one frame to
+    // the next. Falls back to a full sort when there is too much to move.
+    let in_order = |a: f32, b: f32| if front_to_back { a <= b } else { a >= b };
+    let mut budget = list.len() * 4;
+    for i in 1..list.len() {
+        let mut j = i;
+        while j > 0 && !in_order(list[j - 1].1, list[j].1) {
+            if budget == 0 {
+                list.sort_by(|a, b| if front_to_back { a.1.total_cmp(&b.1) } else { b.1.total_cmp(&a.1) });
+                return;
+            }
+            list.swap(j - 1, j);
+            budget -= 1;
+            j -= 1;
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RSGViewportRenderList {
+    pub viewport_node_key: RSGNodeKey,
+    // as set on the viewport component, see viewport_rect() for resolving it
+    pub rect: Option<RSGViewportRect>,
+    // None when the viewport has no usable camera, the lists are empty then
+    pub camera_node_key: Option<RSGNodeKey>,
+    // as set on the viewport component
+    pub culling: bool,
+    // front to back
+    pub opaque_list: RSGRenderList,
+    // back to front
+    pub alpha_list: RSGRenderList,
+    // renderables outside the camera's view frustum
+    pub culled_list: Vec<RSGNodeKey>,
+    // renderables (culled or not) whose material property values changed since
+    // the previous prepare_scene, the ones collected again in it are not listed
+    pub material_values_changed: Vec<RSGNodeKey>
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RSGCullingStats {
+    pub visible: usize,
+    pub culled: usize
+}
+
+impl RSGViewportRenderList {
+    pub fn culling_stats(&self) -> RSGCullingStats {
+        RSGCullingStats {
+            visible: self.opaque_list.len() + self.alpha_list.len(),
+            culled: self.culled_list.len()
+        }
+    }
+}
+
+// in tree order (pre-order), so an outer viewport comes before the ones nested in it
+pub type RSGViewportRenderLists = Vec<RSGViewportRenderList>;
+
 pub fn prepare_scene<ObserverT>(
     components: &mut RSGComponentContainer,
     scene: &RSGScene<RSGComponentLinks, ObserverT>,
-    dirty_world_roots: &[RSGNodeKey],
-    dirty_opacity_roots: &[RSGNodeKey],
-    opaque_list: &mut RSGRenderList,
-    alpha_list: &mut RSGRenderList,
+    dirty: &mut RSGSceneObserver,
+    render_lists: &mut RSGViewportRenderLists,
     work_list: &mut Vec<RSGNodeKey>,
     pool: &scoped_pool::Pool)
     where ObserverT: RSGObserver + Sync
 {
-    pool.scoped(|scope| {
+    // The render lists are kept from the previous call and get patched based on
+    // the changes collected by dirty, normalized here first so that keys removed
+    // or detached after being marked dirty, and nested roots, are dropped (see
+    // RSGSceneObserver::normalize(), doing it again is cheap):
+    // - the renderables are collected again only in the subtrees that were added,
+    //   removed, detached, became visible or fully transparent, and for the viewports
+    //   that changed their camera, and the nodes with a changed mesh,
+    // - moved renderables, and all of them for a moved or changed camera or a
+    //   change in the culling, are culled and sorted again,
+    // - renderables with a changed material or inherited opacity move between
+    //   the opaque and alpha lists when needed.
+    // Removals also cost a pass over the existing entries to drop the stale ones.
+    dirty.normalize(scene);
+    let dirty: &RSGSceneObserver = dirty;
+    let mut moved: std::collections::HashSet<RSGNodeKey> = Default::default();
+    let mut moved_cameras: std::collections::HashSet<RSGCameraKey> = Default::default();
+    let (visibility_changes, opacity_changed_renderables) = pool.scoped(|scope| {
+        // The opacities are independent from the world transforms, so they get
+        // updated in parallel with those.
+        let opacities = std::mem::take(&mut components.opacities);
+        let dirty_opacity_roots = &dirty.dirty_opacity_roots;
         let (opacity_tx, opacity_rx) = std::sync::mpsc::channel();
-        if !dirty_opacity_roots.is_empty() {
-            let opacities = std::mem::replace(&mut components.opacities, Default::default());
-            scope.execute(move || {
-                opacity_tx.send(update_inherited_opacities(opacities, scene, dirty_opacity_roots)).unwrap();
+        scope.execute(move || {
+            let mut visibility_changes = vec![];
+            let mut changed_renderables = vec![];
+            let opacities = update_inherited_opacities(opacities, scene, dirty_opacity_roots,
+                &mut visibility_changes, &mut changed_renderables);
+            opacity_tx.send((opacities, visibility_changes, changed_renderables)).unwrap();
+        });
+
+        // Disjoint dirty subtrees are independent so they can be processed on
+        // different threads. The results are written back in the order of the
+        // roots afterwards, so the outcome does not depend on the scheduling.
+        // This relies on normalized roots, a nested one would start from a stale parent.
+        let world_roots = dirty.dirty_world_roots.as_slice();
+        let job_count = world_roots.len().min(pool.workers() * 4).max(1);
+        let mut results: smallvec::SmallVec<[(RSGWorldTransformList, Vec<RSGNodeKey>); 16]> = smallvec::smallvec![];
+        results.resize_with(job_count, Default::default);
+        let transforms = &components.transforms;
+        if job_count > 1 {
+            let chunk_size = world_roots.len().div_ceil(job_count);
+            scope.zoom(|scope| {
+                for (roots, (result, moved_renderables)) in world_roots.chunks(chunk_size).zip(results.iter_mut()) {
+                    scope.execute(move || calculate_world_transforms(transforms, scene, roots, result, moved_renderables));
+                }
             });
+        } else {
+            let (result, moved_renderables) = &mut results[0];
+            calculate_world_transforms(transforms, scene, world_roots, result, moved_renderables);
+        }
+        for (result, moved_renderables) in &results {
+            for (transform_key, camera_key, world_transform) in result {
+                components.transforms[*transform_key].world_transform = *world_transform;
+                if let Some(camera_key) = camera_key {
+                    update_camera_world(&mut components.cameras[*camera_key], world_transform);
+                    moved_cameras.insert(*camera_key);
+                }
+            }
+            moved.extend(moved_renderables.iter().copied());
         }
 
-        work_list.clear();
-        let (viewport_tx, viewport_rx) = std::sync::mpsc::channel();
-        scope.execute(move || {
-            let renderable_candidates = work_list;
-            let mut viewport_nodes: smallvec::SmallVec<[(RSGNodeKey, usize); 16]> = smallvec::smallvec![];
-            for (key, node) in scene.iter() {
-                if node.get_component_links().viewport_key.is_some() {
-                    let mut renderable_count: usize = 0;
-                    for (child_key, _) in scene.traverse(key) {
-                        let child_links = scene.get_component_links(child_key);
-                        if child_links.viewport_key.is_some() && child_key != key {
-                            // ###
-                            unimplemented!();
-                        }
-                        if child_links.mesh_key.is_some() && child_links.transform_key.is_some() {
-                            renderable_candidates.push(child_key);
-                            renderable_count += 1;
-                        }
-                    }
-                    viewport_nodes.push((key, renderable_count));
+        // cameras that changed without their world transform changing
+        for camera_node_key in &dirty.dirty_camera_nodes {
+            if let Some(camera_key) = scene.get_component_links(*camera_node_key).camera_key {
+                let world_transform = camera_world_transform(&components.transforms, scene, *camera_node_key);
+                update_camera_world(&mut components.cameras[camera_key], &world_transform);
+                moved_cameras.insert(camera_key);
+            }
+        }
+
+        let (opacities, visibility_changes, changed_renderables) = opacity_rx.recv().unwrap();
+        components.opacities = opacities;
+        (visibility_changes, changed_renderables)
+    });
+
+    // Lists no longer matching the camera of their viewport component get
+    // collected again, and so do the nodes that got a viewport component.
+    let mut relist: RSGDirtySubtreeRootList = smallvec::smallvec![];
+    let mut culling_changed: RSGDirtySubtreeRootList = smallvec::smallvec![];
+    for render_list in render_lists.iter_mut() {
+        let viewport_node_key = render_list.viewport_node_key;
+        if !scene.is_reachable(viewport_node_key) {
+            // dropped together with the removed or detached subtree below
+            continue;
+        }
+        match scene.get_component_links(viewport_node_key).viewport_key {
+            Some(viewport_key) => {
+                let viewport = components.viewports[viewport_key];
+                if viewport_camera(scene, &viewport) != render_list.camera_node_key {
+                    relist.push(viewport_node_key);
+                    continue;
                 }
+                if viewport.culling != render_list.culling {
+                    render_list.culling = viewport.culling;
+                    culling_changed.push(viewport_node_key);
+                }
+                render_list.rect = viewport.rect;
             }
-            viewport_tx.send((renderable_candidates, viewport_nodes)).unwrap();
-        });
+            None => relist.push(viewport_node_key)
+        }
+    }
+    for key in &dirty.dirty_viewport_nodes {
+        if scene.get_component_links(*key).viewport_key.is_some() && !render_lists.iter().any(|r| r.viewport_node_key == *key) {
+            relist.push(*key);
+        }
+    }
 
-        for subtree_root_key in dirty_world_roots {
-            for (key, _) in scene.traverse(*subtree_root_key) {
-                let links = scene.get_component_links(key);
-                if let Some(transform_key) = links.transform_key {
-                    let mut world_transform = components.transforms[transform_key].local_transform;
-                    for key in scene.ancestors(key) {
-                        if let Some(transform_key) = scene.get_component_links(key).transform_key {
-                            world_transform *= components.transforms[transform_key].world_transform;
-                            break;
-                        }
-                    }
-                    components.transforms[transform_key].world_transform = world_transform;
-                    if let Some(camera_key) = links.camera_key {
-                        let cam_prop = calculate_camera_world_properties(&world_transform);
-                        components.cameras[camera_key].world_properties = cam_prop;
-                    }
+    // (key, whole subtree or just the node), outer subtrees first
+    let mut resync_roots: smallvec::SmallVec<[(RSGNodeKey, bool); 16]> = smallvec::smallvec![];
+    resync_roots.extend(dirty.removed_roots.iter()
+        .filter(|key| scene.is_linked(**key) || scene.is_detached(**key))
+        .map(|key| (*key, true)));
+    resync_roots.extend(dirty.added_roots.iter().chain(visibility_changes.iter()).chain(relist.iter()).map(|key| (*key, true)));
+    resync_roots.extend(dirty.dirty_mesh_nodes.iter().map(|key| (*key, false)));
+    resync_roots.sort_by_cached_key(|(key, subtree)| (!*subtree, scene.depth(*key)));
+
+    // drop what is there for them now...
+    let mut cleared_subtrees: std::collections::HashSet<RSGNodeKey> = Default::default();
+    let mut cleared_nodes: std::collections::HashSet<RSGNodeKey> = Default::default();
+    let mut collect_roots: smallvec::SmallVec<[(RSGNodeKey, bool); 16]> = smallvec::smallvec![];
+    for (root_key, subtree) in resync_roots {
+        if scene.ancestors_with_node(root_key).any(|key| cleared_subtrees.contains(&key)) {
+            continue;
+        }
+        if subtree {
+            for (key, _) in scene.traverse(root_key) {
+                if let Some(mesh_key) = scene.get_component_links(key).mesh_key {
+                    components.meshes[mesh_key].viewport_node_key = None;
                 }
+                cleared_subtrees.insert(key);
+            }
+        } else {
+            if let Some(mesh_key) = scene.get_component_links(root_key).mesh_key {
+                components.meshes[mesh_key].viewport_node_key = None;
             }
+            cleared_nodes.insert(root_key);
+        }
+        if scene.is_reachable(root_key) {
+            collect_roots.push((root_key, subtree));
         }
+    }
+    if !cleared_subtrees.is_empty() || !cleared_nodes.is_empty() || !dirty.removed_roots.is_empty() {
+        let keep = |key: RSGNodeKey| scene.is_linked(key) && !cleared_subtrees.contains(&key) && !cleared_nodes.contains(&key);
+        render_lists.retain(|r| scene.is_linked(r.viewport_node_key) && !cleared_subtrees.contains(&r.viewport_node_key));
+        for render_list in render_lists.iter_mut() {
+            render_list.opaque_list.retain(|e| keep(e.0));
+            render_list.alpha_list.retain(|e| keep(e.0));
+            render_list.culled_list.retain(|key| keep(*key));
+        }
+    }
+    if dirty.hierarchy_changed && render_lists.len() > 1 {
+        render_lists.sort_by(|a, b| tree_order(scene, a.viewport_node_key, b.viewport_node_key));
+    }
 
-        opaque_list.clear();
-        alpha_list.clear();
-        let mut renderable_idx = 0;
-
-        if !dirty_opacity_roots.is_empty() {
-            components.opacities = opacity_rx.recv().unwrap();
-        }
-        let (renderable_candidates, viewport_nodes) = viewport_rx.recv().unwrap();
-
-        for (viewport_node_key, renderable_count) in &viewport_nodes {
-            let viewport_key = scene.get_component_links(*viewport_node_key).viewport_key.unwrap();
-            if let Some(cam_node_key) = components.viewports[viewport_key].camera_node_key {
-                let cam_links = scene.get_component_links(cam_node_key);
-                let cam_props = components.cameras[cam_links.camera_key.unwrap()].world_properties;
-                for i in renderable_idx..renderable_idx + renderable_count {
-                    let key = renderable_candidates[i];
-                    let links = scene.get_component_links(key);
-                    components.meshes[links.mesh_key.unwrap()].viewport_node_key = Some(*viewport_node_key);
-                    let sort_dist = calculate_sorting_distance(
-                        &components.transforms[links.transform_key.unwrap()].world_transform,
-                        &components.mesh_data[links.mesh_key.unwrap()].bounds,
-                        &cam_props);
-                    if components.is_opaque(links) {
-                        // front to back
-                        let pos = opaque_list.binary_search_by(|e| e.1.partial_cmp(&sort_dist).unwrap()).unwrap_or_else(|i| i);
-                        opaque_list.insert(pos, (key, sort_dist));
-                    } else {
-                        // back to front
-                        let pos = alpha_list.binary_search_by(|e| sort_dist.partial_cmp(&e.1).unwrap()).unwrap_or_else(|i| i);
-                        alpha_list.insert(pos, (key, sort_dist));
-                    }
+    // ...and collect the renderables again, skipping fully transparent subtrees
+    let opacities = &components.opacities;
+    let is_transparent = |links: &RSGComponentLinks| links.opacity_key.is_some_and(|key| opacities[key].inherited_opacity <= 0.0);
+    let renderable_candidates = work_list;
+    renderable_candidates.clear();
+    // (owning viewport node, number of candidates) for consecutive runs in renderable_candidates
+    let mut candidate_runs: smallvec::SmallVec<[(RSGNodeKey, usize); 16]> = smallvec::smallvec![];
+    let mut new_viewport_nodes: RSGDirtySubtreeRootList = smallvec::smallvec![];
+    for (root_key, subtree) in collect_roots {
+        // the nearest ancestor with an opacity component tells, since its inherited
+        // opacity is 0 for all the nodes below a fully transparent one
+        if scene.ancestors(root_key).find_map(|key| scene.get_component_links(key).opacity_key)
+            .is_some_and(|opacity_key| opacities[opacity_key].inherited_opacity <= 0.0)
+        {
+            continue;
+        }
+        let outer_viewport_node_key = scene.ancestors(root_key).find(|key| scene.get_component_links(*key).viewport_key.is_some());
+        // (depth, viewport node) for the viewports on the current path
+        let mut viewport_stack: smallvec::SmallVec<[(u32, RSGNodeKey); 8]> = smallvec::smallvec![];
+        scene.visit(root_key, |key, depth| {
+            while viewport_stack.last().is_some_and(|e| e.0 >= depth) {
+                viewport_stack.pop();
+            }
+            let links = scene.get_component_links(key);
+            if is_transparent(links) {
+                return RSGVisitResult::SkipChildren;
+            }
+            if links.viewport_key.is_some() {
+                viewport_stack.push((depth, key));
+                if subtree {
+                    new_viewport_nodes.push(key);
                 }
-            } else {
-                for i in renderable_idx..renderable_idx + renderable_count {
-                    components.meshes[scene.get_component_links(renderable_candidates[i]).mesh_key.unwrap()].viewport_node_key = None;
+            }
+            if links.mesh_key.is_some() && links.transform_key.is_some() {
+                if let Some(viewport_node_key) = viewport_stack.last().map(|e| e.1).or(outer_viewport_node_key) {
+                    match candidate_runs.last_mut() {
+                        Some(run) if run.0 == viewport_node_key => run.1 += 1,
+                        _ => candidate_runs.push((viewport_node_key, 1))
+                    }
+                    renderable_candidates.push(key);
                 }
             }
-            renderable_idx += renderable_count;
+            if subtree { RSGVisitResult::Continue } else { RSGVisitResult::SkipChildren }
+        });
+    }
+    for viewport_node_key in new_viewport_nodes {
+        let viewport = components.viewports[scene.get_component_links(viewport_node_key).viewport_key.unwrap()];
+        let render_list = RSGViewportRenderList {
+            viewport_node_key,
+            rect: viewport.rect,
+            camera_node_key: viewport_camera(scene, &viewport),
+            culling: viewport.culling,
+            opaque_list: vec![],
+            alpha_list: vec![],
+            culled_list: vec![],
+            material_values_changed: vec![]
+        };
+        let idx = render_lists.partition_point(|r| tree_order(scene, r.viewport_node_key, viewport_node_key) == std::cmp::Ordering::Less);
+        render_lists.insert(idx, render_list);
+    }
+    let mut candidate_idx = 0;
+    for (viewport_node_key, candidate_count) in candidate_runs {
+        let candidates = &renderable_candidates[candidate_idx..candidate_idx + candidate_count];
+        candidate_idx += candidate_count;
+        // a viewport without a camera has no renderables assigned
+        if let Some(render_list) = render_lists.iter_mut().find(|r| r.viewport_node_key == viewport_node_key && r.camera_node_key.is_some()) {
+            for &key in candidates {
+                components.meshes[scene.get_component_links(key).mesh_key.unwrap()].viewport_node_key = Some(viewport_node_key);
+                // gets culled and sorted below, like the ones that moved
+                render_list.culled_list.push(key);
+                moved.insert(key);
+            }
         }
-    });
+    }
+
+    // Aspect ratios follow the viewport rects, clipped to the viewports above. This
+    // is done once per camera, before culling, so all the viewports sharing a camera
+    // use the same matrices. The first viewport in tree order decides, the others
+    // render with that. Empty rects and orthographic cameras without a ymag are
+    // skipped, and so are viewports covering the whole target since its size is not
+    // known here.
+    let mut aspect_ratio_cameras: std::collections::HashSet<RSGCameraKey> = Default::default();
+    for render_list in render_lists.iter() {
+        let cam_node_key = match render_list.camera_node_key {
+            Some(cam_node_key) => cam_node_key,
+            None => continue
+        };
+        let camera_key = scene.get_component_links(cam_node_key).camera_key.unwrap();
+        if !components.cameras[camera_key].aspect_ratio_from_viewport || !aspect_ratio_cameras.insert(camera_key) {
+            continue;
+        }
+        let rect = components.clipped_viewport_rect(scene, render_list.viewport_node_key);
+        if let Some(rect) = rect.filter(|rect| rect.w > 0 && rect.h > 0) {
+            let aspect_ratio = rect.w as f32 / rect.h as f32;
+            if components.cameras[camera_key].camera.aspect_ratio().is_some_and(|current| current != aspect_ratio) {
+                let world_transform = camera_world_transform(&components.transforms, scene, cam_node_key);
+                let camera = &mut components.cameras[camera_key];
+                camera.camera.set_aspect_ratio(aspect_ratio);
+                camera.update_matrices(&world_transform);
+                moved_cameras.insert(camera_key);
+            }
+        }
+    }
+
+    let mut reclassify: std::collections::HashSet<RSGNodeKey> = dirty.dirty_material_nodes.iter().copied().collect();
+    reclassify.extend(opacity_changed_renderables);
+    let viewport_of = |key: &RSGNodeKey| scene.get_component_links(*key).mesh_key
+        .and_then(|mesh_key| components.meshes[mesh_key].viewport_node_key);
+    let affected_viewports: std::collections::HashSet<RSGNodeKey> = moved.iter().chain(reclassify.iter()).filter_map(viewport_of).collect();
+    for render_list in render_lists.iter_mut() {
+        render_list.material_values_changed.clear();
+        if let Some(cam_node_key) = render_list.camera_node_key {
+            let camera_key = scene.get_component_links(cam_node_key).camera_key.unwrap();
+            let all_moved = moved_cameras.contains(&camera_key) || culling_changed.contains(&render_list.viewport_node_key);
+            if all_moved || affected_viewports.contains(&render_list.viewport_node_key) {
+                update_render_list(components, scene, render_list, &components.cameras[camera_key], &moved, &reclassify, all_moved);
+            }
+        }
+    }
+    for key in dirty.dirty_material_value_nodes.iter().filter(|key| !cleared_subtrees.contains(*key) && !cleared_nodes.contains(*key)) {
+        if let Some(viewport_node_key) = viewport_of(key) {
+            if let Some(render_list) = render_lists.iter_mut().find(|r| r.viewport_node_key == viewport_node_key) {
+                render_list.material_values_changed.push(*key);
+            }
+        }
+    }
 }
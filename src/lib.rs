@@ -8,3 +8,4 @@ pub mod viewport;
 pub mod components;
 pub mod observer;
 pub mod sampler;
+pub mod undo;
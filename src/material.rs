@@ -4,7 +4,7 @@ slotmap::new_key_type! {
     pub struct RSGMaterialKey;
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Default)]
 pub struct RSGMaterialComponent {
 }
 
@@ -5,7 +5,7 @@ slotmap::new_key_type! {
     pub struct RSGMeshKey;
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Default)]
 pub struct RSGMeshComponent {
     pub viewport_node_key: Option<RSGNodeKey>
 }
@@ -102,6 +102,22 @@ impl RSGAabb {
     pub fn center(&self) -> glm::Vec3 {
         (self.minimum + self.maximum) * 0.5
     }
+
+    pub fn transformed(&self, m: &glm::Mat4) -> RSGAabb {
+        // the box around the transformed corners
+        let mut result = RSGAabb { minimum: glm::vec3(f32::MAX, f32::MAX, f32::MAX), maximum: glm::vec3(f32::MIN, f32::MIN, f32::MIN) };
+        for i in 0..8 {
+            let corner = glm::vec4(
+                if i & 1 == 0 { self.minimum.x } else { self.maximum.x },
+                if i & 2 == 0 { self.minimum.y } else { self.maximum.y },
+                if i & 4 == 0 { self.minimum.z } else { self.maximum.z },
+                1.0);
+            let p = glm::vec4_to_vec3(&(m * corner));
+            result.minimum = glm::min2(&result.minimum, &p);
+            result.maximum = glm::max2(&result.maximum, &p);
+        }
+        result
+    }
 }
 
 impl Default for RSGAabb {
@@ -6,11 +6,16 @@ pub type RSGDirtySubtreeRootList = smallvec::SmallVec<[RSGNodeKey; 16]>;
 pub struct RSGSceneObserver {
     pub changed: bool,
     pub hierarchy_changed: bool,
+    pub added_roots: RSGDirtySubtreeRootList,
+    // removed or detached, the keys may no longer be valid
+    pub removed_roots: RSGDirtySubtreeRootList,
     pub dirty_world_roots: RSGDirtySubtreeRootList,
     pub dirty_opacity_roots: RSGDirtySubtreeRootList,
     pub dirty_material_nodes: RSGDirtySubtreeRootList,
     pub dirty_material_value_nodes: RSGDirtySubtreeRootList,
-    pub dirty_mesh_nodes: RSGDirtySubtreeRootList
+    pub dirty_mesh_nodes: RSGDirtySubtreeRootList,
+    pub dirty_camera_nodes: RSGDirtySubtreeRootList,
+    pub dirty_viewport_nodes: RSGDirtySubtreeRootList
 }
 
 impl RSGObserver for RSGSceneObserver {
@@ -19,19 +24,42 @@ impl RSGObserver for RSGSceneObserver {
         match event {
             RSGEvent::SubtreeAddedOrReattached(key) => {
                 self.hierarchy_changed = true;
+                self.added_roots.push(key);
                 self.dirty_world_roots.push(key);
                 self.dirty_opacity_roots.push(key);
                 self.dirty_material_nodes.push(key);
                 self.dirty_material_value_nodes.push(key);
                 self.dirty_mesh_nodes.push(key);
             }
-            RSGEvent::SubtreeAboutToBeRemoved(_) => self.hierarchy_changed = true,
-            RSGEvent::Dirty(key, flags) if flags.contains(RSGDirtyFlags::TRANSFORM) => self.dirty_world_roots.push(key),
-            RSGEvent::Dirty(key, flags) if flags.contains(RSGDirtyFlags::OPACITY) => self.dirty_opacity_roots.push(key),
-            RSGEvent::Dirty(key, flags) if flags.contains(RSGDirtyFlags::MATERIAL) => self.dirty_material_nodes.push(key),
-            RSGEvent::Dirty(key, flags) if flags.contains(RSGDirtyFlags::MATERIAL_VALUES) => self.dirty_material_value_nodes.push(key),
-            RSGEvent::Dirty(key, flags) if flags.contains(RSGDirtyFlags::MESH) => self.dirty_mesh_nodes.push(key),
-            _ => {}
+            RSGEvent::SubtreeAboutToBeRemoved(key) | RSGEvent::SubtreeAboutToBeTemporarilyDetached(key) => {
+                self.hierarchy_changed = true;
+                self.removed_roots.push(key);
+            }
+            RSGEvent::ChildrenReordered(_) => self.hierarchy_changed = true,
+            RSGEvent::Dirty(key, flags) => {
+                // every flag counts, mark_dirty(key, TRANSFORM | OPACITY) dirties both
+                if flags.contains(RSGDirtyFlags::TRANSFORM) {
+                    self.dirty_world_roots.push(key);
+                }
+                if flags.contains(RSGDirtyFlags::OPACITY) {
+                    self.dirty_opacity_roots.push(key);
+                }
+                if flags.contains(RSGDirtyFlags::MATERIAL) {
+                    self.dirty_material_nodes.push(key);
+                }
+                if flags.contains(RSGDirtyFlags::MATERIAL_VALUES) {
+                    self.dirty_material_value_nodes.push(key);
+                }
+                if flags.contains(RSGDirtyFlags::MESH) {
+                    self.dirty_mesh_nodes.push(key);
+                }
+                if flags.contains(RSGDirtyFlags::CAMERA) {
+                    self.dirty_camera_nodes.push(key);
+                }
+                if flags.contains(RSGDirtyFlags::VIEWPORT) {
+                    self.dirty_viewport_nodes.push(key);
+                }
+            }
         }
     }
 }
@@ -44,10 +72,151 @@ impl RSGSceneObserver {
     pub fn reset(&mut self) {
         self.changed = false;
         self.hierarchy_changed = false;
+        self.added_roots.clear();
+        self.removed_roots.clear();
         self.dirty_world_roots.clear();
         self.dirty_opacity_roots.clear();
         self.dirty_material_nodes.clear();
         self.dirty_material_value_nodes.clear();
         self.dirty_mesh_nodes.clear();
+        self.dirty_camera_nodes.clear();
+        self.dirty_viewport_nodes.clear();
+    }
+
+    pub fn normalize<CompLinksT, ObserverT>(&mut self, scene: &RSGScene<CompLinksT, ObserverT>)
+        where CompLinksT: Default + Copy, ObserverT: RSGObserver
+    {
+        // To be called once the changes for the frame are done, prepare_scene does it too.
+        normalize_dirty_roots(scene, &mut self.added_roots);
+        let mut seen = std::collections::HashSet::new();
+        self.removed_roots.retain(|key| seen.insert(*key));
+        normalize_dirty_roots(scene, &mut self.dirty_world_roots);
+        normalize_dirty_roots(scene, &mut self.dirty_opacity_roots);
+        normalize_dirty_nodes(scene, &mut self.dirty_material_nodes);
+        normalize_dirty_nodes(scene, &mut self.dirty_material_value_nodes);
+        normalize_dirty_nodes(scene, &mut self.dirty_mesh_nodes);
+        normalize_dirty_nodes(scene, &mut self.dirty_camera_nodes);
+        normalize_dirty_nodes(scene, &mut self.dirty_viewport_nodes);
+    }
+}
+
+pub fn normalize_dirty_nodes<CompLinksT, ObserverT>(scene: &RSGScene<CompLinksT, ObserverT>, keys: &mut RSGDirtySubtreeRootList)
+    where CompLinksT: Default + Copy, ObserverT: RSGObserver
+{
+    // drops duplicates and nodes no longer in the scene (removed or detached
+    // after being marked dirty), keeping the order of the first occurrences
+    let mut seen = std::collections::HashSet::new();
+    keys.retain(|key| scene.is_reachable(*key) && seen.insert(*key));
+}
+
+pub fn normalize_dirty_roots<CompLinksT, ObserverT>(scene: &RSGScene<CompLinksT, ObserverT>, keys: &mut RSGDirtySubtreeRootList)
+    where CompLinksT: Default + Copy, ObserverT: RSGObserver
+{
+    // like normalize_dirty_nodes, and drops the roots that are in the subtree
+    // of another root, since updating that subtree covers them (both checked
+    // with a single walk up from each key)
+    let mut seen = std::collections::HashSet::new();
+    keys.retain(|key| scene.is_linked(*key) && seen.insert(*key));
+    keys.retain(|key| {
+        let mut top_key = *key;
+        for ancestor_key in scene.ancestors(*key) {
+            if seen.contains(&ancestor_key) {
+                return false;
+            }
+            top_key = ancestor_key;
+        }
+        scene.root() == Some(top_key)
+    });
+}
+
+// Fan-out: several independent consumers can observe the same scene, either
+// statically as a tuple of observers, or dynamically via RSGObserverList.
+// Each observer receives all events, in order. To keep access to its state
+// after handing it over, a subsystem can register an Arc<Mutex<T>>.
+
+macro_rules! impl_observer_for_tuple {
+    ($($name:ident $idx:tt),+) => {
+        impl<$($name),+> RSGObserver for ($($name,)+) where $($name: RSGObserver),+ {
+            fn notify(&mut self, event: RSGEvent) {
+                $(self.$idx.notify(event);)+
+            }
+        }
+    }
+}
+
+impl_observer_for_tuple!(A 0, B 1);
+impl_observer_for_tuple!(A 0, B 1, C 2);
+impl_observer_for_tuple!(A 0, B 1, C 2, D 3);
+
+impl<T> RSGObserver for Box<T> where T: RSGObserver + ?Sized {
+    fn notify(&mut self, event: RSGEvent) {
+        (**self).notify(event);
+    }
+}
+
+impl<T> RSGObserver for std::sync::Arc<std::sync::Mutex<T>> where T: RSGObserver {
+    fn notify(&mut self, event: RSGEvent) {
+        self.lock().unwrap().notify(event);
+    }
+}
+
+impl<CompLinksT, T> RSGHierarchyObserver<CompLinksT> for std::sync::Arc<std::sync::Mutex<T>> where T: RSGHierarchyObserver<CompLinksT> {
+    fn notify_hierarchy(&mut self, event: RSGHierarchyEvent<CompLinksT>) {
+        self.lock().unwrap().notify_hierarchy(event);
+    }
+
+    fn wants_removed_component_links(&self) -> bool {
+        self.lock().unwrap().wants_removed_component_links()
+    }
+}
+
+slotmap::new_key_type! {
+    pub struct RSGObserverHandle;
+}
+
+pub type RSGBoxedObserver = Box<dyn RSGObserver + Send + Sync>;
+
+#[derive(Default)]
+pub struct RSGObserverList {
+    observers: slotmap::DenseSlotMap<RSGObserverHandle, RSGBoxedObserver>,
+    order: Vec<RSGObserverHandle>
+}
+
+impl RSGObserver for RSGObserverList {
+    fn notify(&mut self, event: RSGEvent) {
+        // in subscription order
+        for handle in &self.order {
+            self.observers[*handle].notify(event);
+        }
+    }
+}
+
+impl RSGObserverList {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn subscribe(&mut self, observer: RSGBoxedObserver) -> RSGObserverHandle {
+        let handle = self.observers.insert(observer);
+        self.order.push(handle);
+        handle
+    }
+
+    pub fn unsubscribe(&mut self, handle: RSGObserverHandle) -> Option<RSGBoxedObserver> {
+        let observer = self.observers.remove(handle)?;
+        self.order.retain(|h| *h != handle);
+        Some(observer)
+    }
+
+    pub fn is_subscribed(&self, handle: RSGObserverHandle) -> bool {
+        self.observers.contains_key(handle)
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
     }
 }
@@ -11,7 +11,7 @@ pub struct RSGOpacityComponent {
 impl RSGOpacityComponent {
     pub fn new(opacity: f32) -> Self {
         RSGOpacityComponent {
-            opacity: opacity,
+            opacity,
             inherited_opacity: opacity
         }
     }
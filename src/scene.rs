@@ -19,21 +19,152 @@ pub enum RSGEvent {
     SubtreeAddedOrReattached(RSGNodeKey),
     SubtreeAboutToBeRemoved(RSGNodeKey),
     SubtreeAboutToBeTemporarilyDetached(RSGNodeKey),
+    ChildrenReordered(RSGNodeKey),
     Dirty(RSGNodeKey, RSGDirtyFlags)
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RSGSceneError {
+    NoRoot,
+    RootAlreadySet,
+    InvalidNode(RSGNodeKey),
+    NodeNotClean,
+    RootNotAllowed(RSGNodeKey),
+    NotAChild(RSGNodeKey, RSGNodeKey),
+    MoveIntoOwnSubtree(RSGNodeKey, RSGNodeKey),
+    DetachedNode(RSGNodeKey),
+    InvalidName
+}
+
+impl std::fmt::Display for RSGSceneError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RSGSceneError::NoRoot => write!(f, "scene has no root"),
+            RSGSceneError::RootAlreadySet => write!(f, "scene already has a root"),
+            RSGSceneError::InvalidNode(key) => write!(f, "{:?} is not a valid node in the scene", key),
+            RSGSceneError::NodeNotClean => write!(f, "node is already linked"),
+            RSGSceneError::RootNotAllowed(key) => write!(f, "operation not allowed on the root {:?}", key),
+            RSGSceneError::NotAChild(key, parent_key) => write!(f, "{:?} is not a child of {:?}", key, parent_key),
+            RSGSceneError::MoveIntoOwnSubtree(key, parent_key) => write!(f, "cannot move {:?} under its own descendant {:?}", key, parent_key),
+            RSGSceneError::DetachedNode(key) => write!(f, "{:?} is in a detached subtree", key),
+            RSGSceneError::InvalidName => write!(f, "names must be non-empty, without '/', not starting with '#', and not \".\" or \"..\"")
+        }
+    }
+}
+
+impl std::error::Error for RSGSceneError {}
+
+pub type RSGSceneResult<T> = Result<T, RSGSceneError>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RSGNodeLink {
+    Parent,
+    FirstChild,
+    LastChild,
+    PrevSibling,
+    NextSibling
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RSGSceneViolation {
+    // the key field of the node does not match its slot
+    KeyMismatch(RSGNodeKey, Option<RSGNodeKey>),
+    // the link refers to a node that is not in the arena
+    DanglingLink(RSGNodeKey, RSGNodeLink, RSGNodeKey),
+    // the root has a parent or siblings
+    RootLinked(RSGNodeKey),
+    // the child is in the child list of the parent but does not point back to it
+    ParentMismatch(RSGNodeKey, RSGNodeKey),
+    // the prev sibling link of the node is not the node before it in the child list
+    PrevSiblingMismatch(RSGNodeKey, Option<RSGNodeKey>),
+    // the last child link of the parent is not the end of its child list
+    LastChildMismatch(RSGNodeKey),
+    // the child list of the node loops, or the node is in more than one child list
+    Cycle(RSGNodeKey),
+    // the node is not in the subtree of the root or of a detached node,
+    // e.g. it has no parent without being detached
+    Unreachable(RSGNodeKey)
+}
+
+// Detailed hierarchy events, delivered to the RSGHierarchyObserver in addition
+// to the matching RSGEvent, for observers that mirror the structure and cannot
+// query the scene (e.g. after a removal). Placements are the links after an add,
+// and before a detach/removal.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RSGNodePlacement {
+    pub parent_key: Option<RSGNodeKey>,
+    pub prev_sibling_key: Option<RSGNodeKey>,
+    pub next_sibling_key: Option<RSGNodeKey>
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum RSGHierarchyEvent<'a, CompLinksT> {
+    SubtreeAddedOrReattached(RSGNodeKey, RSGNodePlacement),
+    // also used for subtrees that are removed but kept alive, e.g. for undo
+    SubtreeAboutToBeTemporarilyDetached(RSGNodeKey, RSGNodePlacement),
+    // the component links of the nodes leaving the scene for good, only
+    // collected when the observer wants_removed_component_links(), empty otherwise
+    SubtreeAboutToBeRemoved(RSGNodeKey, RSGNodePlacement, &'a [CompLinksT]),
+    // parent key and the children in their new order
+    SiblingOrderChanged(RSGNodeKey, &'a [RSGNodeKey])
+}
+
+impl<'a, CompLinksT> RSGHierarchyEvent<'a, CompLinksT> {
+    pub fn component_links(&self) -> Option<&'a [CompLinksT]> {
+        match self {
+            RSGHierarchyEvent::SubtreeAboutToBeRemoved(_, _, links) => Some(links),
+            _ => None
+        }
+    }
+}
+
 pub trait RSGObserver {
     fn notify(&mut self, event: RSGEvent);
 }
 
+// Opt-in receiver of the RSGHierarchyEvents, registered with
+// RSGScene::set_hierarchy_observer(). Separate from RSGObserver since the
+// events carry the component links, i.e. depend on the scene's CompLinksT.
+pub trait RSGHierarchyObserver<CompLinksT> {
+    fn notify_hierarchy(&mut self, event: RSGHierarchyEvent<CompLinksT>);
+
+    // Collecting the component links for SubtreeAboutToBeRemoved walks the
+    // removed subtree, so it is only done when the observer asks for them.
+    fn wants_removed_component_links(&self) -> bool {
+        false
+    }
+}
+
+pub type RSGBoxedHierarchyObserver<CompLinksT> = Box<dyn RSGHierarchyObserver<CompLinksT> + Send + Sync>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RSGNodePosition {
+    Append,
+    Prepend,
+    Before(RSGNodeKey),
+    After(RSGNodeKey)
+}
+
+#[deprecated(note = "use RSGNodePosition")]
 #[derive(Debug)]
 pub enum RSGSubtreeAddOp {
     Append,
     Prepend
 }
 
+#[allow(deprecated)]
+impl From<RSGSubtreeAddOp> for RSGNodePosition {
+    fn from(op: RSGSubtreeAddOp) -> Self {
+        match op {
+            RSGSubtreeAddOp::Append => RSGNodePosition::Append,
+            RSGSubtreeAddOp::Prepend => RSGNodePosition::Prepend
+        }
+    }
+}
+
 pub struct RSGSubtreeAddTransaction {
-    entries: smallvec::SmallVec<[(RSGNodeKey, RSGNodeKey, RSGSubtreeAddOp); 16]>,
+    entries: smallvec::SmallVec<[(RSGNodeKey, RSGNodeKey, RSGNodePosition); 16]>,
     #[cfg(debug_assertions)]
     possible_parent_keys: std::collections::HashSet<RSGNodeKey>
 }
@@ -48,6 +179,64 @@ impl RSGSubtreeAddTransaction {
     }
 }
 
+impl Default for RSGSubtreeAddTransaction {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct RSGSceneTransaction {
+    saved_links: std::collections::HashMap<RSGNodeKey, RSGNodeLinks>,
+    new_node_keys: std::collections::HashSet<RSGNodeKey>,
+    new_node_keys_in_order: smallvec::SmallVec<[RSGNodeKey; 16]>,
+    relinked_node_keys: smallvec::SmallVec<[RSGNodeKey; 16]>,
+    removed_node_keys: smallvec::SmallVec<[RSGNodeKey; 16]>
+}
+
+impl RSGSceneTransaction {
+    pub fn new() -> Self {
+        RSGSceneTransaction {
+            saved_links: std::collections::HashMap::new(),
+            new_node_keys: std::collections::HashSet::new(),
+            new_node_keys_in_order: smallvec::SmallVec::new(),
+            relinked_node_keys: smallvec::SmallVec::new(),
+            removed_node_keys: smallvec::SmallVec::new()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.new_node_keys.is_empty() && self.relinked_node_keys.is_empty() && self.removed_node_keys.is_empty()
+    }
+}
+
+impl Default for RSGSceneTransaction {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Detached subtrees keep their arena entries and keys but are not part of the
+// scene: they are not traversed from the root and their nodes are not valid
+// until reattached. Not Copy/Clone, so each detach is reattached or removed once.
+#[must_use]
+#[derive(Debug, PartialEq, Eq)]
+pub struct RSGDetachedSubtree {
+    node_key: RSGNodeKey
+}
+
+impl RSGDetachedSubtree {
+    pub fn node_key(&self) -> RSGNodeKey {
+        self.node_key
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RSGVisitResult {
+    Continue,
+    SkipChildren,
+    Stop
+}
+
 enum RSGIterState {
     AcceptAndVisitChildren(RSGNodeKey, u32),
     VisitSiblings(RSGNodeKey, u32)
@@ -59,6 +248,17 @@ pub struct RSGIter<'a, CompLinksT, ObserverT> where CompLinksT: Copy {
     next: Option<RSGIterState>
 }
 
+impl<'a, CompLinksT, ObserverT> RSGIter<'a, CompLinksT, ObserverT> where CompLinksT: Default + Copy, ObserverT: RSGObserver {
+    pub fn skip_children(&mut self) {
+        // do not descend into the node returned by the last call to next()
+        if let Some(RSGIterState::AcceptAndVisitChildren(node_key, depth)) = self.next {
+            if depth > 0 {
+                self.next = Some(RSGIterState::VisitSiblings(self.scene.arena[node_key].parent_key.unwrap(), depth - 1));
+            }
+        }
+    }
+}
+
 impl<'a, CompLinksT, ObserverT> Iterator for RSGIter<'a, CompLinksT, ObserverT> where CompLinksT: Default + Copy, ObserverT: RSGObserver {
     type Item = (RSGNodeKey, u32);
     fn next(&mut self) -> Option<Self::Item> {
@@ -80,7 +280,87 @@ impl<'a, CompLinksT, ObserverT> Iterator for RSGIter<'a, CompLinksT, ObserverT>
                 _ => self.next = None
             }
         }
-        return None;
+        None
+    }
+}
+
+pub struct RSGBreadthFirstIter<'a, CompLinksT, ObserverT> where CompLinksT: Copy {
+    scene: &'a RSGScene<CompLinksT, ObserverT>,
+    queue: std::collections::VecDeque<(RSGNodeKey, u32)>
+}
+
+impl<'a, CompLinksT, ObserverT> Iterator for RSGBreadthFirstIter<'a, CompLinksT, ObserverT> where CompLinksT: Default + Copy, ObserverT: RSGObserver {
+    type Item = (RSGNodeKey, u32);
+    fn next(&mut self) -> Option<Self::Item> {
+        let (node_key, depth) = self.queue.pop_front()?;
+        let mut child_node_key_opt = self.scene.arena[node_key].first_child_key;
+        while let Some(key) = child_node_key_opt {
+            self.queue.push_back((key, depth + 1));
+            child_node_key_opt = self.scene.arena[key].next_sibling_key;
+        }
+        Some((node_key, depth))
+    }
+}
+
+pub struct RSGPostOrderIter<'a, CompLinksT, ObserverT> where CompLinksT: Copy {
+    scene: &'a RSGScene<CompLinksT, ObserverT>,
+    start_key: RSGNodeKey,
+    next: Option<(RSGNodeKey, u32)>
+}
+
+impl<'a, CompLinksT, ObserverT> RSGPostOrderIter<'a, CompLinksT, ObserverT> where CompLinksT: Default + Copy, ObserverT: RSGObserver {
+    fn first_leaf(scene: &RSGScene<CompLinksT, ObserverT>, node_key: RSGNodeKey, depth: u32) -> (RSGNodeKey, u32) {
+        let mut result = (node_key, depth);
+        while let Some(key) = scene.arena[result.0].first_child_key {
+            result = (key, result.1 + 1);
+        }
+        result
+    }
+}
+
+impl<'a, CompLinksT, ObserverT> Iterator for RSGPostOrderIter<'a, CompLinksT, ObserverT> where CompLinksT: Default + Copy, ObserverT: RSGObserver {
+    type Item = (RSGNodeKey, u32);
+    fn next(&mut self) -> Option<Self::Item> {
+        let (node_key, depth) = self.next.take()?;
+        if node_key != self.start_key {
+            let node = &self.scene.arena[node_key];
+            self.next = match node.next_sibling_key {
+                Some(key) => Some(Self::first_leaf(self.scene, key, depth)),
+                None => Some((node.parent_key.unwrap(), depth - 1))
+            };
+        }
+        Some((node_key, depth))
+    }
+}
+
+pub struct RSGReverseIter<'a, CompLinksT, ObserverT> where CompLinksT: Copy {
+    scene: &'a RSGScene<CompLinksT, ObserverT>,
+    start_key: RSGNodeKey,
+    next: Option<(RSGNodeKey, u32)>
+}
+
+impl<'a, CompLinksT, ObserverT> RSGReverseIter<'a, CompLinksT, ObserverT> where CompLinksT: Default + Copy, ObserverT: RSGObserver {
+    fn last_leaf(scene: &RSGScene<CompLinksT, ObserverT>, node_key: RSGNodeKey, depth: u32) -> (RSGNodeKey, u32) {
+        let mut result = (node_key, depth);
+        while let Some(key) = scene.arena[result.0].last_child_key {
+            result = (key, result.1 + 1);
+        }
+        result
+    }
+}
+
+impl<'a, CompLinksT, ObserverT> Iterator for RSGReverseIter<'a, CompLinksT, ObserverT> where CompLinksT: Default + Copy, ObserverT: RSGObserver {
+    type Item = (RSGNodeKey, u32);
+    fn next(&mut self) -> Option<Self::Item> {
+        let (node_key, depth) = self.next.take()?;
+        if node_key != self.start_key {
+            let node = &self.scene.arena[node_key];
+            self.next = match node.prev_sibling_key {
+                Some(key) => Some(Self::last_leaf(self.scene, key, depth)),
+                None => Some((node.parent_key.unwrap(), depth - 1))
+            };
+        }
+        Some((node_key, depth))
     }
 }
 
@@ -102,6 +382,49 @@ impl<'a, CompLinksT, ObserverT> Iterator for RSGAncestorIter<'a, CompLinksT, Obs
     }
 }
 
+pub type RSGNodeLinks = (Option<RSGNodeKey>, Option<RSGNodeKey>, Option<RSGNodeKey>, Option<RSGNodeKey>, Option<RSGNodeKey>, Option<RSGNodeKey>);
+
+pub struct RSGChildIter<'a, CompLinksT, ObserverT> where CompLinksT: Copy {
+    scene: &'a RSGScene<CompLinksT, ObserverT>,
+    next: Option<RSGNodeKey>
+}
+
+impl<'a, CompLinksT, ObserverT> Iterator for RSGChildIter<'a, CompLinksT, ObserverT> where CompLinksT: Default + Copy, ObserverT: RSGObserver {
+    type Item = RSGNodeKey;
+    fn next(&mut self) -> Option<RSGNodeKey> {
+        let key = self.next.take()?;
+        self.next = self.scene.arena[key].next_sibling_key;
+        Some(key)
+    }
+}
+
+pub struct RSGChildRevIter<'a, CompLinksT, ObserverT> where CompLinksT: Copy {
+    scene: &'a RSGScene<CompLinksT, ObserverT>,
+    next: Option<RSGNodeKey>
+}
+
+impl<'a, CompLinksT, ObserverT> Iterator for RSGChildRevIter<'a, CompLinksT, ObserverT> where CompLinksT: Default + Copy, ObserverT: RSGObserver {
+    type Item = RSGNodeKey;
+    fn next(&mut self) -> Option<RSGNodeKey> {
+        let key = self.next.take()?;
+        self.next = self.scene.arena[key].prev_sibling_key;
+        Some(key)
+    }
+}
+
+pub struct RSGSiblingIter<'a, CompLinksT, ObserverT> where CompLinksT: Copy {
+    children: RSGChildIter<'a, CompLinksT, ObserverT>,
+    node_key: RSGNodeKey
+}
+
+impl<'a, CompLinksT, ObserverT> Iterator for RSGSiblingIter<'a, CompLinksT, ObserverT> where CompLinksT: Default + Copy, ObserverT: RSGObserver {
+    type Item = RSGNodeKey;
+    fn next(&mut self) -> Option<RSGNodeKey> {
+        let node_key = self.node_key;
+        self.children.find(|key| *key != node_key)
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct RSGNode<CompLinksT> where CompLinksT: Copy {
     pub key: Option<RSGNodeKey>,
@@ -110,6 +433,8 @@ pub struct RSGNode<CompLinksT> where CompLinksT: Copy {
     last_child_key: Option<RSGNodeKey>,
     prev_sibling_key: Option<RSGNodeKey>,
     next_sibling_key: Option<RSGNodeKey>,
+    // root of a subtree taken out of the tree with its keys kept alive
+    detached: bool,
     comp_links: CompLinksT
 }
 
@@ -122,6 +447,7 @@ impl<CompLinksT> RSGNode<CompLinksT> where CompLinksT: Default + Copy {
             last_child_key: None,
             prev_sibling_key: None,
             next_sibling_key: None,
+            detached: false,
             comp_links: Default::default()
         }
     }
@@ -134,7 +460,8 @@ impl<CompLinksT> RSGNode<CompLinksT> where CompLinksT: Default + Copy {
             last_child_key: None,
             prev_sibling_key: None,
             next_sibling_key: None,
-            comp_links: comp_links
+            detached: false,
+            comp_links
         }
     }
 
@@ -142,9 +469,10 @@ impl<CompLinksT> RSGNode<CompLinksT> where CompLinksT: Default + Copy {
         self.key.is_none() && self.parent_key.is_none()
             && self.first_child_key.is_none() && self.last_child_key.is_none()
             && self.prev_sibling_key.is_none() && self.next_sibling_key.is_none()
+            && !self.detached
     }
 
-    pub fn links(&self) -> (Option<RSGNodeKey>, Option<RSGNodeKey>, Option<RSGNodeKey>, Option<RSGNodeKey>, Option<RSGNodeKey>, Option<RSGNodeKey>) {
+    pub fn links(&self) -> RSGNodeLinks {
         (self.key, self.parent_key, self.first_child_key, self.last_child_key, self.prev_sibling_key, self.next_sibling_key)
     }
 
@@ -157,10 +485,19 @@ impl<CompLinksT> RSGNode<CompLinksT> where CompLinksT: Default + Copy {
     }
 }
 
+impl<CompLinksT> Default for RSGNode<CompLinksT> where CompLinksT: Default + Copy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub struct RSGScene<CompLinksT, ObserverT> where CompLinksT: Copy {
     arena: slotmap::SlotMap<RSGNodeKey, RSGNode<CompLinksT>>,
     root_key: Option<RSGNodeKey>,
-    observer: Option<ObserverT>
+    observer: Option<ObserverT>,
+    hierarchy_observer: Option<RSGBoxedHierarchyObserver<CompLinksT>>,
+    names: slotmap::SecondaryMap<RSGNodeKey, String>,
+    name_index: std::collections::HashMap<String, smallvec::SmallVec<[RSGNodeKey; 4]>>
 }
 
 impl<CompLinksT, ObserverT> RSGScene<CompLinksT, ObserverT> where CompLinksT: Default + Copy, ObserverT: RSGObserver {
@@ -168,7 +505,10 @@ impl<CompLinksT, ObserverT> RSGScene<CompLinksT, ObserverT> where CompLinksT: De
         RSGScene {
             arena: slotmap::SlotMap::with_key(),
             root_key: None,
-            observer: None
+            observer: None,
+            hierarchy_observer: None,
+            names: slotmap::SecondaryMap::new(),
+            name_index: std::collections::HashMap::new()
         }
     }
 
@@ -177,8 +517,23 @@ impl<CompLinksT, ObserverT> RSGScene<CompLinksT, ObserverT> where CompLinksT: De
     }
 
     pub fn take_observer(&mut self) -> Option<ObserverT> {
-        let observer = self.observer.take();
-        observer
+        self.observer.take()
+    }
+
+    pub fn observer(&self) -> Option<&ObserverT> {
+        self.observer.as_ref()
+    }
+
+    pub fn observer_mut(&mut self) -> Option<&mut ObserverT> {
+        self.observer.as_mut()
+    }
+
+    pub fn set_hierarchy_observer(&mut self, observer: RSGBoxedHierarchyObserver<CompLinksT>) {
+        self.hierarchy_observer = Some(observer);
+    }
+
+    pub fn take_hierarchy_observer(&mut self) -> Option<RSGBoxedHierarchyObserver<CompLinksT>> {
+        self.hierarchy_observer.take()
     }
 
     fn notify(&mut self, event: RSGEvent) {
@@ -187,16 +542,66 @@ impl<CompLinksT, ObserverT> RSGScene<CompLinksT, ObserverT> where CompLinksT: De
         }
     }
 
+    fn placement_of(&self, node_key: RSGNodeKey) -> RSGNodePlacement {
+        let node = &self.arena[node_key];
+        RSGNodePlacement {
+            parent_key: node.parent_key,
+            prev_sibling_key: node.prev_sibling_key,
+            next_sibling_key: node.next_sibling_key
+        }
+    }
+
+    fn notify_added(&mut self, node_key: RSGNodeKey) {
+        let placement = self.placement_of(node_key);
+        self.notify(RSGEvent::SubtreeAddedOrReattached(node_key));
+        if let Some(obs) = self.hierarchy_observer.as_mut() {
+            obs.notify_hierarchy(RSGHierarchyEvent::SubtreeAddedOrReattached(node_key, placement));
+        }
+    }
+
+    fn notify_detaching(&mut self, node_key: RSGNodeKey, placement: RSGNodePlacement) {
+        self.notify(RSGEvent::SubtreeAboutToBeTemporarilyDetached(node_key));
+        if let Some(obs) = self.hierarchy_observer.as_mut() {
+            obs.notify_hierarchy(RSGHierarchyEvent::SubtreeAboutToBeTemporarilyDetached(node_key, placement));
+        }
+    }
+
+    fn notify_removing<F>(&mut self, node_key: RSGNodeKey, placement: RSGNodePlacement, mut skip: F) where F: FnMut(RSGNodeKey) -> bool {
+        // the subtree is collected as currently linked, minus the subtrees for which skip() returns true
+        self.notify(RSGEvent::SubtreeAboutToBeRemoved(node_key));
+        let wants_component_links = match self.hierarchy_observer.as_ref() {
+            Some(obs) => obs.wants_removed_component_links(),
+            None => return
+        };
+        let mut component_links: smallvec::SmallVec<[CompLinksT; 16]> = smallvec::smallvec![];
+        if wants_component_links {
+            self.visit(node_key, |key, _| {
+                if key != node_key && skip(key) {
+                    return RSGVisitResult::SkipChildren;
+                }
+                component_links.push(self.arena[key].comp_links);
+                RSGVisitResult::Continue
+            });
+        }
+        let obs = self.hierarchy_observer.as_mut().unwrap();
+        obs.notify_hierarchy(RSGHierarchyEvent::SubtreeAboutToBeRemoved(node_key, placement, &component_links));
+    }
+
     pub fn set_root(&mut self, node: RSGNode<CompLinksT>) -> RSGNodeKey {
         assert!(self.root_key.is_none());
         debug_assert!(node.is_clean());
         let key = self.arena.insert(node);
         self.root_key = Some(key);
         self.arena[key].key = self.root_key;
-        self.notify(RSGEvent::SubtreeAddedOrReattached(key));
+        self.notify_added(key);
         key
     }
 
+    fn free_node(&mut self, node_key: RSGNodeKey) -> RSGNode<CompLinksT> {
+        self.clear_name(node_key);
+        self.arena.remove(node_key).unwrap()
+    }
+
     pub fn root(&self) -> Option<RSGNodeKey> {
         self.root_key
     }
@@ -206,12 +611,32 @@ impl<CompLinksT, ObserverT> RSGScene<CompLinksT, ObserverT> where CompLinksT: De
     }
 
     pub fn is_valid(&self, node_key: RSGNodeKey) -> bool {
+        // the node is in the arena and connected to the root through all its
+        // ancestors (false for every node of a detached subtree, whether detached
+        // by detach(), a pending removal in a transaction or an undoable removal,
+        // and for nodes pending in a RSGSubtreeAddTransaction)
+        self.is_linked(node_key) && self.ancestors_with_node(node_key).last() == self.root_key
+    }
+
+    pub fn is_reachable(&self, node_key: RSGNodeKey) -> bool {
+        // same as is_valid(), reads better where the hierarchy is what matters
+        self.is_valid(node_key)
+    }
+
+    pub(crate) fn is_linked(&self, node_key: RSGNodeKey) -> bool {
+        // O(1) part of is_valid(): the node is in the arena and has a parent or
+        // is the root, which is enough when the ancestors are known to be valid
         match self.arena.get(node_key) {
             Some(node) => node.parent_key.is_some() || node.key == self.root_key,
             None => false
         }
     }
 
+    pub fn is_detached(&self, node_key: RSGNodeKey) -> bool {
+        // the node is the root of a detached subtree
+        self.arena.get(node_key).is_some_and(|node| node.detached)
+    }
+
     pub fn get_component_links(&self, node_key: RSGNodeKey) -> &CompLinksT {
         self.arena.get(node_key).unwrap().get_component_links()
     }
@@ -227,7 +652,7 @@ impl<CompLinksT, ObserverT> RSGScene<CompLinksT, ObserverT> where CompLinksT: De
             if parent_node.first_child_key.is_none() {
                 parent_node.first_child_key = Some(node_key);
             }
-            old_last_node_key = std::mem::replace(&mut parent_node.last_child_key, Some(node_key));
+            old_last_node_key = parent_node.last_child_key.replace(node_key);
         }
         {
             let new_node = self.arena.get_mut(node_key).unwrap();
@@ -236,8 +661,8 @@ impl<CompLinksT, ObserverT> RSGScene<CompLinksT, ObserverT> where CompLinksT: De
             new_node.prev_sibling_key = old_last_node_key;
             new_node.next_sibling_key = None; // in case links are unclean due to being called from remove_without_children()
         }
-        if old_last_node_key.is_some() {
-            let old_last_node = self.arena.get_mut(old_last_node_key.unwrap()).unwrap();
+        if let Some(old_last_node_key) = old_last_node_key {
+            let old_last_node = self.arena.get_mut(old_last_node_key).unwrap();
             debug_assert!(old_last_node.next_sibling_key.is_none());
             old_last_node.next_sibling_key = Some(node_key);
         }
@@ -250,7 +675,7 @@ impl<CompLinksT, ObserverT> RSGScene<CompLinksT, ObserverT> where CompLinksT: De
         debug_assert!(node.is_clean() && self.is_valid(parent_key));
         let node_key = self.arena.insert(node);
         self.append_impl(parent_key, node_key);
-        self.notify(RSGEvent::SubtreeAddedOrReattached(node_key));
+        self.notify_added(node_key);
         node_key
     }
 
@@ -258,7 +683,7 @@ impl<CompLinksT, ObserverT> RSGScene<CompLinksT, ObserverT> where CompLinksT: De
         let old_first_node_key;
         {
             let parent_node = self.arena.get_mut(parent_key).unwrap();
-            old_first_node_key = std::mem::replace(&mut parent_node.first_child_key, Some(node_key));
+            old_first_node_key = parent_node.first_child_key.replace(node_key);
             if parent_node.last_child_key.is_none() {
                 parent_node.last_child_key = Some(node_key);
             }
@@ -270,8 +695,8 @@ impl<CompLinksT, ObserverT> RSGScene<CompLinksT, ObserverT> where CompLinksT: De
             new_node.prev_sibling_key = None;
             new_node.next_sibling_key = old_first_node_key;
         }
-        if old_first_node_key.is_some() {
-            let old_first_node = self.arena.get_mut(old_first_node_key.unwrap()).unwrap();
+        if let Some(old_first_node_key) = old_first_node_key {
+            let old_first_node = self.arena.get_mut(old_first_node_key).unwrap();
             debug_assert!(old_first_node.prev_sibling_key.is_none());
             old_first_node.prev_sibling_key = Some(node_key);
         }
@@ -284,7 +709,7 @@ impl<CompLinksT, ObserverT> RSGScene<CompLinksT, ObserverT> where CompLinksT: De
         debug_assert!(node.is_clean() && self.is_valid(parent_key));
         let node_key = self.arena.insert(node);
         self.prepend_impl(parent_key, node_key);
-        self.notify(RSGEvent::SubtreeAddedOrReattached(node_key));
+        self.notify_added(node_key);
         node_key
     }
 
@@ -293,7 +718,7 @@ impl<CompLinksT, ObserverT> RSGScene<CompLinksT, ObserverT> where CompLinksT: De
         let old_prev_sibling_key;
         {
             let before_node = self.arena.get_mut(before_key).unwrap();
-            old_prev_sibling_key = std::mem::replace(&mut before_node.prev_sibling_key, Some(node_key));
+            old_prev_sibling_key = before_node.prev_sibling_key.replace(node_key);
         }
         {
             let new_node = self.arena.get_mut(node_key).unwrap();
@@ -302,8 +727,8 @@ impl<CompLinksT, ObserverT> RSGScene<CompLinksT, ObserverT> where CompLinksT: De
             new_node.prev_sibling_key = old_prev_sibling_key;
             new_node.next_sibling_key = Some(before_key);
         }
-        if old_prev_sibling_key.is_some() {
-            let old_prev_node = self.arena.get_mut(old_prev_sibling_key.unwrap()).unwrap();
+        if let Some(old_prev_sibling_key) = old_prev_sibling_key {
+            let old_prev_node = self.arena.get_mut(old_prev_sibling_key).unwrap();
             old_prev_node.next_sibling_key = Some(node_key);
         } else {
             let parent_node = self.arena.get_mut(parent_key.unwrap()).unwrap();
@@ -320,7 +745,7 @@ impl<CompLinksT, ObserverT> RSGScene<CompLinksT, ObserverT> where CompLinksT: De
         debug_assert!(node.is_clean() && self.is_valid(before_key));
         let node_key = self.arena.insert(node);
         self.insert_before_impl(before_key, node_key);
-        self.notify(RSGEvent::SubtreeAddedOrReattached(node_key));
+        self.notify_added(node_key);
         node_key
     }
 
@@ -329,7 +754,7 @@ impl<CompLinksT, ObserverT> RSGScene<CompLinksT, ObserverT> where CompLinksT: De
         let old_next_sibling_key;
         {
             let after_node = self.arena.get_mut(after_key).unwrap();
-            old_next_sibling_key = std::mem::replace(&mut after_node.next_sibling_key, Some(node_key));
+            old_next_sibling_key = after_node.next_sibling_key.replace(node_key);
         }
         {
             let new_node = self.arena.get_mut(node_key).unwrap();
@@ -338,8 +763,8 @@ impl<CompLinksT, ObserverT> RSGScene<CompLinksT, ObserverT> where CompLinksT: De
             new_node.prev_sibling_key = Some(after_key);
             new_node.next_sibling_key = old_next_sibling_key;
         }
-        if old_next_sibling_key.is_some() {
-            let old_next_node = self.arena.get_mut(old_next_sibling_key.unwrap()).unwrap();
+        if let Some(old_next_sibling_key) = old_next_sibling_key {
+            let old_next_node = self.arena.get_mut(old_next_sibling_key).unwrap();
             old_next_node.prev_sibling_key = Some(node_key);
         } else {
             let parent_node = self.arena.get_mut(parent_key.unwrap()).unwrap();
@@ -356,20 +781,51 @@ impl<CompLinksT, ObserverT> RSGScene<CompLinksT, ObserverT> where CompLinksT: De
         debug_assert!(node.is_clean() && self.is_valid(after_key));
         let node_key = self.arena.insert(node);
         self.insert_after_impl(after_key, node_key);
-        self.notify(RSGEvent::SubtreeAddedOrReattached(node_key));
+        self.notify_added(node_key);
         node_key
     }
 
+    fn link_impl(&mut self, parent_key: RSGNodeKey, node_key: RSGNodeKey, position: RSGNodePosition) {
+        match position {
+            RSGNodePosition::Append => self.append_impl(parent_key, node_key),
+            RSGNodePosition::Prepend => self.prepend_impl(parent_key, node_key),
+            RSGNodePosition::Before(before_key) => {
+                assert!(self.arena[before_key].parent_key == Some(parent_key));
+                self.insert_before_impl(before_key, node_key)
+            }
+            RSGNodePosition::After(after_key) => {
+                assert!(self.arena[after_key].parent_key == Some(parent_key));
+                self.insert_after_impl(after_key, node_key)
+            }
+        }
+    }
+
+    pub fn move_subtree(&mut self, node_key: RSGNodeKey, new_parent_key: RSGNodeKey, position: RSGNodePosition) {
+        // A(NODE(B), C) -> A(C(NODE(B))) if new_parent_key == C.key and position is Append
+        // Notifies: detach NODE, add NODE
+
+        assert!(node_key != self.root_key.unwrap());
+        debug_assert!(self.is_valid(node_key) && self.is_valid(new_parent_key));
+        assert!(!self.ancestors_with_node(new_parent_key).any(|key| key == node_key));
+        if let RSGNodePosition::Before(sibling_key) | RSGNodePosition::After(sibling_key) = position {
+            assert!(sibling_key != node_key);
+        }
+
+        self.notify_detaching(node_key, self.placement_of(node_key));
+        self.unlink_impl(node_key);
+        self.link_impl(new_parent_key, node_key, position);
+        self.notify_added(node_key);
+    }
+
     #[inline]
-    fn record_add_transaction(&mut self, op: RSGSubtreeAddOp, parent_key: RSGNodeKey, node: RSGNode<CompLinksT>, transaction: &mut RSGSubtreeAddTransaction) -> RSGNodeKey {
+    fn record_add_transaction(&mut self, position: RSGNodePosition, parent_key: RSGNodeKey, node: RSGNode<CompLinksT>, transaction: &mut RSGSubtreeAddTransaction) -> RSGNodeKey {
         debug_assert!(node.is_clean());
-        debug_assert!(!transaction.entries.is_empty() || self.is_valid(parent_key));
 
         #[cfg(debug_assertions)]
-        debug_assert!(transaction.entries.is_empty() || transaction.possible_parent_keys.contains(&parent_key));
+        debug_assert!(transaction.possible_parent_keys.contains(&parent_key) || self.is_valid(parent_key));
 
         let node_key = self.arena.insert(node);
-        transaction.entries.push((parent_key, node_key, op));
+        transaction.entries.push((parent_key, node_key, position));
 
         #[cfg(debug_assertions)]
         transaction.possible_parent_keys.insert(node_key);
@@ -378,161 +834,527 @@ impl<CompLinksT, ObserverT> RSGScene<CompLinksT, ObserverT> where CompLinksT: De
     }
 
     pub fn append_with_transaction(&mut self, parent_key: RSGNodeKey, node: RSGNode<CompLinksT>, transaction: &mut RSGSubtreeAddTransaction) -> RSGNodeKey {
-        self.record_add_transaction(RSGSubtreeAddOp::Append, parent_key, node, transaction)
+        self.record_add_transaction(RSGNodePosition::Append, parent_key, node, transaction)
     }
 
     pub fn prepend_with_transaction(&mut self, parent_key: RSGNodeKey, node: RSGNode<CompLinksT>, transaction: &mut RSGSubtreeAddTransaction) -> RSGNodeKey {
-        self.record_add_transaction(RSGSubtreeAddOp::Prepend, parent_key, node, transaction)
+        self.record_add_transaction(RSGNodePosition::Prepend, parent_key, node, transaction)
+    }
+
+    pub fn insert_with_transaction(&mut self, parent_key: RSGNodeKey, node: RSGNode<CompLinksT>, position: RSGNodePosition, transaction: &mut RSGSubtreeAddTransaction) -> RSGNodeKey {
+        self.record_add_transaction(position, parent_key, node, transaction)
     }
 
     pub fn commit(&mut self, transaction: RSGSubtreeAddTransaction) {
         // A(B, C) -> A(B, C, NODE(NODE2)) if transaction contains two Appends
-        // (atomic subtree add: notifies only for the subtree root)
+        // (atomic subtree add: notifies only for the subtree roots, i.e. the
+        // nodes added under a node that was already in the tree)
         // Notifies: add NODE
 
-        let mut subtree_root_key_opt: Option<RSGNodeKey> = None;
-        for (parent_key, node_key, op) in transaction.entries {
-            match op {
-                RSGSubtreeAddOp::Append => self.append_impl(parent_key, node_key),
-                RSGSubtreeAddOp::Prepend => self.prepend_impl(parent_key, node_key)
-            }
-            if subtree_root_key_opt.is_none() {
-                subtree_root_key_opt = Some(node_key);
-            }
+        let subtree_root_keys: smallvec::SmallVec<[RSGNodeKey; 16]> = transaction.entries.iter()
+            .filter(|(parent_key, _, _)| self.is_linked(*parent_key))
+            .map(|(_, node_key, _)| *node_key)
+            .collect();
+        for (parent_key, node_key, position) in transaction.entries {
+            self.link_impl(parent_key, node_key, position);
         }
-        if let Some(subtree_root_key) = subtree_root_key_opt {
-            self.notify(RSGEvent::SubtreeAddedOrReattached(subtree_root_key));
+        for subtree_root_key in subtree_root_keys {
+            self.notify_added(subtree_root_key);
         }
     }
 
-    pub fn rollback(&mut self, transaction: RSGSubtreeAddTransaction) {
+    pub fn rollback(&mut self, transaction: RSGSubtreeAddTransaction) -> smallvec::SmallVec<[CompLinksT; 16]> {
+        let mut component_links = smallvec::smallvec![];
         for (_, node_key, _) in transaction.entries {
-            self.arena.remove(node_key);
+            component_links.push(self.free_node(node_key).comp_links);
         }
+        component_links
     }
 
-    pub fn remove(&mut self, node_key: RSGNodeKey) -> CompLinksT {
-        // A(NODE(B, C), D) -> A(D)
-        // Notifies: remove NODE
+    pub fn position_of(&self, node_key: RSGNodeKey) -> RSGNodePosition {
+        // position relative to the current siblings, usable to link the node back later
+        match self[node_key].prev_sibling_key {
+            Some(prev_sibling_key) => RSGNodePosition::After(prev_sibling_key),
+            None => RSGNodePosition::Prepend
+        }
+    }
+
+    fn unlink_subtree_impl(&mut self, node_key: RSGNodeKey) {
+        self.unlink_impl(node_key);
+        let node = self.arena.get_mut(node_key).unwrap();
+        node.parent_key = None;
+        node.prev_sibling_key = None;
+        node.next_sibling_key = None;
+        node.detached = true;
+    }
 
-        self.remove_helper(node_key, true)
+    fn relink_subtree_impl(&mut self, node_key: RSGNodeKey, parent_key: RSGNodeKey, position: RSGNodePosition) {
+        self.arena[node_key].detached = false;
+        self.link_impl(parent_key, node_key, position);
     }
 
-    fn remove_helper(&mut self, node_key: RSGNodeKey, with_children: bool) -> CompLinksT {
+    pub(crate) fn unlink_subtree(&mut self, node_key: RSGNodeKey) {
+        // A(NODE(B), C) -> A(C), NODE(B) stays in the arena
+        // (reported as a detach since the nodes and their components are kept)
+        // Notifies: detach NODE
+
         assert!(node_key != self.root_key.unwrap());
+        debug_assert!(self.is_valid(node_key));
+        self.notify_detaching(node_key, self.placement_of(node_key));
+        self.unlink_subtree_impl(node_key);
+    }
 
-        if with_children {
-            self.notify(RSGEvent::SubtreeAboutToBeRemoved(node_key));
-        } else {
-            let node = self.arena.get_mut(node_key).unwrap();
-            node.first_child_key = None;
-            node.last_child_key = None;
-            self.notify(RSGEvent::SubtreeAboutToBeRemoved(node_key));
-        }
+    pub(crate) fn link_subtree(&mut self, node_key: RSGNodeKey, parent_key: RSGNodeKey, position: RSGNodePosition) {
+        // reverse of unlink_subtree()
+        // Notifies: add NODE
 
-        let node = self.arena.remove(node_key).unwrap();
-        let parent_key = node.parent_key.unwrap();
+        debug_assert!(self.is_detached(node_key) && self.is_valid(parent_key));
+        self.relink_subtree_impl(node_key, parent_key, position);
+        self.notify_added(node_key);
+    }
 
-        if node.prev_sibling_key.is_some() && node.next_sibling_key.is_some() {
-            {
-                let prev_sibling_node = self.arena.get_mut(node.prev_sibling_key.unwrap()).unwrap();
-                prev_sibling_node.next_sibling_key = node.next_sibling_key;
-            }
-            {
-                let next_sibling_node = self.arena.get_mut(node.next_sibling_key.unwrap()).unwrap();
-                next_sibling_node.prev_sibling_key = node.prev_sibling_key;
-            }
-        } else if node.prev_sibling_key.is_some() && node.next_sibling_key.is_none() {
-            {
-                let parent_node = self.arena.get_mut(parent_key).unwrap();
-                debug_assert!(node_key == parent_node.last_child_key.unwrap());
-                parent_node.last_child_key = node.prev_sibling_key;
-            }
-            {
-                let prev_sibling_node = self.arena.get_mut(node.prev_sibling_key.unwrap()).unwrap();
-                prev_sibling_node.next_sibling_key = None;
-            }
-        } else if node.prev_sibling_key.is_none() && node.next_sibling_key.is_some() {
-            {
-                let parent_node = self.arena.get_mut(parent_key).unwrap();
-                debug_assert!(node_key == parent_node.first_child_key.unwrap());
-                parent_node.first_child_key = node.next_sibling_key;
-            }
-            {
-                let next_sibling_node = self.arena.get_mut(node.next_sibling_key.unwrap()).unwrap();
-                next_sibling_node.prev_sibling_key = None;
-            }
-        } else {
-            let parent_opt = self.arena.get_mut(parent_key);
-            let parent_node = parent_opt.unwrap();
-            debug_assert!(node_key == parent_node.first_child_key.unwrap());
-            debug_assert!(node_key == parent_node.last_child_key.unwrap());
-            parent_node.first_child_key = None;
-            parent_node.last_child_key = None;
-        }
+    pub(crate) fn remove_unlinked_subtree<F>(&mut self, node_key: RSGNodeKey, mut f: F) where F: FnMut(CompLinksT) {
+        debug_assert!(self.is_detached(node_key));
+        let node = self.free_node(node_key);
+        f(node.comp_links);
+        self.remove_from_arena(node.first_child_key, &mut f);
+    }
 
-        if with_children {
-            self.remove_from_arena(node.first_child_key);
-        }
+    pub fn detach(&mut self, node_key: RSGNodeKey) -> RSGDetachedSubtree {
+        // A(NODE(B), C) -> A(C), NODE(B) stays in the arena with all keys
+        // Notifies: detach NODE
 
-        node.comp_links
+        assert!(node_key != self.root_key.unwrap());
+        debug_assert!(self.is_valid(node_key));
+        self.notify_detaching(node_key, self.placement_of(node_key));
+        self.unlink_subtree_impl(node_key);
+        RSGDetachedSubtree { node_key }
     }
 
-    fn remove_from_arena(&mut self, start_key_opt: Option<RSGNodeKey>) {
-        if start_key_opt.is_none() {
-            return;
-        }
-        let mut stk = smallvec::SmallVec::<[RSGNodeKey; 128]>::new();
-        stk.push(start_key_opt.unwrap());
-        while let Some(mut key) = stk.pop() {
-            loop {
-                let child_node = self.arena.remove(key).unwrap();
-                match child_node.first_child_key {
-                    Some(child_key) => stk.push(child_key),
-                    None => {}
-                }
-                match child_node.next_sibling_key {
-                    Some(sibling_key) => key = sibling_key,
-                    None => break
-                }
-            }
-        }
+    pub fn reattach(&mut self, detached: RSGDetachedSubtree, parent_key: RSGNodeKey, position: RSGNodePosition) -> RSGNodeKey {
+        // A(C), NODE(B) -> A(C, NODE(B)) if position is Append
+        // Notifies: add NODE
+
+        debug_assert!(self.is_detached(detached.node_key) && self.is_valid(parent_key));
+        debug_assert!(!self.ancestors_with_node(parent_key).any(|key| key == detached.node_key));
+        self.relink_subtree_impl(detached.node_key, parent_key, position);
+        self.notify_added(detached.node_key);
+        detached.node_key
     }
 
-    pub fn remove_children(&mut self, node_key: RSGNodeKey) -> smallvec::SmallVec<[CompLinksT; 16]> {
-        // NODE(A, B(C)) -> NODE
-        // Notifies: remove A, remove B
+    pub fn remove_detached(&mut self, detached: RSGDetachedSubtree) -> smallvec::SmallVec<[CompLinksT; 16]> {
+        // destroys the detached subtree without ever reattaching it
+        // Notifies: nothing, the subtree is not in the scene
 
         let mut component_links = smallvec::smallvec![];
-        let mut child_node_key_opt = self.arena[node_key].first_child_key;
-        while let Some(key) = child_node_key_opt {
-            child_node_key_opt = self.arena[key].next_sibling_key;
-            component_links.push(self.remove(key));
-        }
+        self.remove_unlinked_subtree(detached.node_key, |c| component_links.push(c));
         component_links
     }
 
-    pub fn clear(&mut self) -> smallvec::SmallVec<[CompLinksT; 16]> {
-        match self.root_key {
-            Some(key) => self.remove_children(key),
-            None => smallvec::smallvec![]
+    pub fn remove_detached_with_callback<F>(&mut self, detached: RSGDetachedSubtree, f: F) where F: FnMut(CompLinksT) {
+        // f is called with the component links of all nodes in the detached subtree
+        // Notifies: nothing, the subtree is not in the scene
+
+        self.remove_unlinked_subtree(detached.node_key, f);
+    }
+
+    fn save_links(&self, key_opt: Option<RSGNodeKey>, transaction: &mut RSGSceneTransaction) {
+        if let Some(key) = key_opt {
+            if !transaction.new_node_keys.contains(&key) {
+                let links = self.arena[key].links();
+                transaction.saved_links.entry(key).or_insert(links);
+            }
         }
     }
 
-    pub fn insert_under(&mut self, parent_key: RSGNodeKey, node: RSGNode<CompLinksT>) -> RSGNodeKey {
-        // A(B, C(D)) -> A(NODE(B, C(D)))
-        // Notifies: detach B, detach C, add NODE
+    fn save_links_for_link(&self, parent_key: RSGNodeKey, node_key: RSGNodeKey, position: RSGNodePosition, transaction: &mut RSGSceneTransaction) {
+        let parent_node = &self.arena[parent_key];
+        self.save_links(Some(parent_key), transaction);
+        self.save_links(parent_node.first_child_key, transaction);
+        self.save_links(parent_node.last_child_key, transaction);
+        self.save_links(Some(node_key), transaction);
+        match position {
+            RSGNodePosition::Before(before_key) => {
+                self.save_links(Some(before_key), transaction);
+                self.save_links(self.arena[before_key].prev_sibling_key, transaction);
+            }
+            RSGNodePosition::After(after_key) => {
+                self.save_links(Some(after_key), transaction);
+                self.save_links(self.arena[after_key].next_sibling_key, transaction);
+            }
+            _ => {}
+        }
+    }
 
-        debug_assert!(node.is_clean() && self.is_valid(parent_key));
+    fn save_links_for_unlink(&self, node_key: RSGNodeKey, transaction: &mut RSGSceneTransaction) {
+        let node = &self.arena[node_key];
+        self.save_links(Some(node_key), transaction);
+        self.save_links(node.parent_key, transaction);
+        self.save_links(node.prev_sibling_key, transaction);
+        self.save_links(node.next_sibling_key, transaction);
+    }
+
+    fn original_parent_key(&self, node_key: RSGNodeKey, transaction: &RSGSceneTransaction) -> Option<RSGNodeKey> {
+        match transaction.saved_links.get(&node_key) {
+            Some(links) => links.1,
+            None => self.arena[node_key].parent_key
+        }
+    }
+
+    fn original_placement(&self, node_key: RSGNodeKey, transaction: &RSGSceneTransaction) -> RSGNodePlacement {
+        match transaction.saved_links.get(&node_key) {
+            Some(links) => RSGNodePlacement {
+                parent_key: links.1,
+                prev_sibling_key: links.4,
+                next_sibling_key: links.5
+            },
+            None => self.placement_of(node_key)
+        }
+    }
+
+    pub fn insert_in_transaction(&mut self, parent_key: RSGNodeKey, node: RSGNode<CompLinksT>, position: RSGNodePosition, transaction: &mut RSGSceneTransaction) -> RSGNodeKey {
+        // Like the non-transaction variants, but the tree is updated right away
+        // while notifications are deferred until commit_transaction().
+
+        debug_assert!(node.is_clean() && self.is_valid(parent_key));
+        let node_key = self.arena.insert(node);
+        transaction.new_node_keys.insert(node_key);
+        transaction.new_node_keys_in_order.push(node_key);
+        self.save_links_for_link(parent_key, node_key, position, transaction);
+        self.link_impl(parent_key, node_key, position);
+        transaction.relinked_node_keys.push(node_key);
+        node_key
+    }
+
+    pub fn append_in_transaction(&mut self, parent_key: RSGNodeKey, node: RSGNode<CompLinksT>, transaction: &mut RSGSceneTransaction) -> RSGNodeKey {
+        self.insert_in_transaction(parent_key, node, RSGNodePosition::Append, transaction)
+    }
+
+    pub fn prepend_in_transaction(&mut self, parent_key: RSGNodeKey, node: RSGNode<CompLinksT>, transaction: &mut RSGSceneTransaction) -> RSGNodeKey {
+        self.insert_in_transaction(parent_key, node, RSGNodePosition::Prepend, transaction)
+    }
+
+    pub fn insert_before_in_transaction(&mut self, before_key: RSGNodeKey, node: RSGNode<CompLinksT>, transaction: &mut RSGSceneTransaction) -> RSGNodeKey {
+        assert!(before_key != self.root_key.unwrap());
+        let parent_key = self.arena[before_key].parent_key.unwrap();
+        self.insert_in_transaction(parent_key, node, RSGNodePosition::Before(before_key), transaction)
+    }
+
+    pub fn insert_after_in_transaction(&mut self, after_key: RSGNodeKey, node: RSGNode<CompLinksT>, transaction: &mut RSGSceneTransaction) -> RSGNodeKey {
+        assert!(after_key != self.root_key.unwrap());
+        let parent_key = self.arena[after_key].parent_key.unwrap();
+        self.insert_in_transaction(parent_key, node, RSGNodePosition::After(after_key), transaction)
+    }
+
+    pub fn insert_under_in_transaction(&mut self, parent_key: RSGNodeKey, node: RSGNode<CompLinksT>, transaction: &mut RSGSceneTransaction) -> RSGNodeKey {
+        debug_assert!(node.is_clean() && self.is_valid(parent_key));
+        let child_keys = self.child_keys(parent_key);
+        self.save_links(Some(parent_key), transaction);
+        for key in &child_keys {
+            self.save_links(Some(*key), transaction);
+        }
+        let node_key = self.arena.insert(node);
+        transaction.new_node_keys.insert(node_key);
+        transaction.new_node_keys_in_order.push(node_key);
+        self.insert_under_impl(parent_key, node_key);
+        transaction.relinked_node_keys.push(node_key);
+        transaction.relinked_node_keys.extend_from_slice(&child_keys);
+        node_key
+    }
+
+    pub fn move_subtree_in_transaction(&mut self, node_key: RSGNodeKey, new_parent_key: RSGNodeKey, position: RSGNodePosition, transaction: &mut RSGSceneTransaction) {
+        assert!(node_key != self.root_key.unwrap());
+        debug_assert!(self.is_valid(node_key) && self.is_valid(new_parent_key));
+        assert!(!self.ancestors_with_node(new_parent_key).any(|key| key == node_key));
+        if let RSGNodePosition::Before(sibling_key) | RSGNodePosition::After(sibling_key) = position {
+            assert!(sibling_key != node_key);
+        }
+
+        self.save_links_for_unlink(node_key, transaction);
+        self.unlink_impl(node_key);
+        self.save_links_for_link(new_parent_key, node_key, position, transaction);
+        self.link_impl(new_parent_key, node_key, position);
+        transaction.relinked_node_keys.push(node_key);
+    }
+
+    pub fn remove_in_transaction(&mut self, node_key: RSGNodeKey, transaction: &mut RSGSceneTransaction) {
+        // The subtree is unlinked right away, but stays in the arena until
+        // commit_transaction() so that rollback_transaction() can restore it.
+
+        assert!(node_key != self.root_key.unwrap());
+        debug_assert!(self.is_valid(node_key));
+
+        self.save_links_for_unlink(node_key, transaction);
+        self.unlink_subtree_impl(node_key);
+        transaction.removed_node_keys.push(node_key);
+    }
+
+    pub fn commit_transaction(&mut self, transaction: RSGSceneTransaction) -> smallvec::SmallVec<[CompLinksT; 16]> {
+        // A(B(C), D, E) -> A(D(C), F) when moving C under D, removing B and E, appending F
+        // (notifications are coalesced so that only the roots of the affected subtrees
+        // are reported, in terms of the tree before and after the transaction)
+        // Notifies: detach C, remove B, remove E, add C, add F
+        // Returns the component links of all nodes removed from the arena.
+
+        let t = transaction;
+
+        let mut removed_keys = std::collections::HashSet::new();
+        let mut removed_keys_in_order: smallvec::SmallVec<[RSGNodeKey; 16]> = smallvec::smallvec![];
+        for removed_root_key in &t.removed_node_keys {
+            for (key, _) in self.traverse(*removed_root_key) {
+                removed_keys.insert(key);
+                removed_keys_in_order.push(key);
+            }
+        }
+
+        let mut relinked_keys = std::collections::HashSet::new();
+        let mut surviving_relinked_keys: smallvec::SmallVec<[RSGNodeKey; 16]> = smallvec::smallvec![];
+        for key in &t.relinked_node_keys {
+            if !removed_keys.contains(key) && relinked_keys.insert(*key) {
+                surviving_relinked_keys.push(*key);
+            }
+        }
+
+        // existing nodes that moved, unless an original ancestor moved as well
+        let moved_keys: std::collections::HashSet<RSGNodeKey> = surviving_relinked_keys.iter()
+            .filter(|key| !t.new_node_keys.contains(key))
+            .copied()
+            .collect();
+        for key in &surviving_relinked_keys {
+            if moved_keys.contains(key) {
+                let mut ancestor_moved = false;
+                let mut parent_key_opt = self.original_parent_key(*key, &t);
+                while let Some(parent_key) = parent_key_opt {
+                    if moved_keys.contains(&parent_key) {
+                        ancestor_moved = true;
+                        break;
+                    }
+                    parent_key_opt = self.original_parent_key(parent_key, &t);
+                }
+                if !ancestor_moved {
+                    let placement = self.original_placement(*key, &t);
+                    self.notify_detaching(*key, placement);
+                }
+            }
+        }
+
+        // existing nodes that go away, unless the original parent goes away as well
+        let notified_removed_keys: std::collections::HashSet<RSGNodeKey> = removed_keys_in_order.iter()
+            .filter(|key| !t.new_node_keys.contains(key))
+            .filter(|key| match self.original_parent_key(**key, &t) {
+                Some(parent_key) => !removed_keys.contains(&parent_key),
+                None => true
+            })
+            .copied()
+            .collect();
+        for key in &removed_keys_in_order {
+            if notified_removed_keys.contains(key) {
+                let placement = self.original_placement(*key, &t);
+                self.notify_removing(*key, placement, |key| notified_removed_keys.contains(&key));
+            }
+        }
+
+        let mut component_links = smallvec::smallvec![];
+        for key in removed_keys_in_order {
+            component_links.push(self.free_node(key).comp_links);
+        }
+
+        // new and moved nodes, unless an ancestor is new or moved as well
+        for key in &surviving_relinked_keys {
+            if !self.ancestors(*key).any(|ancestor_key| relinked_keys.contains(&ancestor_key)) {
+                self.notify_added(*key);
+            }
+        }
+
+        component_links
+    }
+
+    pub fn rollback_transaction(&mut self, transaction: RSGSceneTransaction) -> smallvec::SmallVec<[CompLinksT; 16]> {
+        // Restores the links of all existing nodes touched by the transaction,
+        // and drops the new nodes. No notifications.
+        // Returns the component links of the new nodes.
+
+        for (key, links) in transaction.saved_links {
+            let node = self.arena.get_mut(key).unwrap();
+            node.key = links.0;
+            node.parent_key = links.1;
+            node.first_child_key = links.2;
+            node.last_child_key = links.3;
+            node.prev_sibling_key = links.4;
+            node.next_sibling_key = links.5;
+            // the transaction only unlinks nodes that were in the tree
+            node.detached = false;
+        }
+        let mut component_links = smallvec::smallvec![];
+        for key in transaction.new_node_keys_in_order {
+            component_links.push(self.free_node(key).comp_links);
+        }
+        component_links
+    }
+
+    pub fn remove(&mut self, node_key: RSGNodeKey) -> CompLinksT {
+        // A(NODE(B, C), D) -> A(D)
+        // Notifies: remove NODE
+
+        self.remove_helper(node_key, true, &mut |_| {})
+    }
+
+    pub fn remove_with_callback<F>(&mut self, node_key: RSGNodeKey, mut f: F) where F: FnMut(CompLinksT) {
+        // A(NODE(B, C), D) -> A(D)
+        // f is called with the component links of NODE, B, C
+        // Notifies: remove NODE
+
+        self.remove_helper(node_key, true, &mut f);
+    }
+
+    fn remove_helper<F>(&mut self, node_key: RSGNodeKey, with_children: bool, f: &mut F) -> CompLinksT where F: FnMut(CompLinksT) {
+        assert!(node_key != self.root_key.unwrap());
+
+        if !with_children {
+            let node = self.arena.get_mut(node_key).unwrap();
+            node.first_child_key = None;
+            node.last_child_key = None;
+        }
+        self.notify_removing(node_key, self.placement_of(node_key), |_| false);
+
+        self.unlink_impl(node_key);
+        let node = self.free_node(node_key);
+
+        f(node.comp_links);
+        if with_children {
+            self.remove_from_arena(node.first_child_key, f);
+        }
+
+        node.comp_links
+    }
+
+    fn unlink_impl(&mut self, node_key: RSGNodeKey) {
+        let node = self.arena[node_key];
+        let parent_key = node.parent_key.unwrap();
+
+        match (node.prev_sibling_key, node.next_sibling_key) {
+            (Some(prev_sibling_key), Some(next_sibling_key)) => {
+                {
+                    let prev_sibling_node = self.arena.get_mut(prev_sibling_key).unwrap();
+                    prev_sibling_node.next_sibling_key = node.next_sibling_key;
+                }
+                {
+                    let next_sibling_node = self.arena.get_mut(next_sibling_key).unwrap();
+                    next_sibling_node.prev_sibling_key = node.prev_sibling_key;
+                }
+            }
+            (Some(prev_sibling_key), None) => {
+                {
+                    let parent_node = self.arena.get_mut(parent_key).unwrap();
+                    debug_assert!(node_key == parent_node.last_child_key.unwrap());
+                    parent_node.last_child_key = node.prev_sibling_key;
+                }
+                {
+                    let prev_sibling_node = self.arena.get_mut(prev_sibling_key).unwrap();
+                    prev_sibling_node.next_sibling_key = None;
+                }
+            }
+            (None, Some(next_sibling_key)) => {
+                {
+                    let parent_node = self.arena.get_mut(parent_key).unwrap();
+                    debug_assert!(node_key == parent_node.first_child_key.unwrap());
+                    parent_node.first_child_key = node.next_sibling_key;
+                }
+                {
+                    let next_sibling_node = self.arena.get_mut(next_sibling_key).unwrap();
+                    next_sibling_node.prev_sibling_key = None;
+                }
+            }
+            (None, None) => {
+                let parent_node = self.arena.get_mut(parent_key).unwrap();
+                debug_assert!(node_key == parent_node.first_child_key.unwrap());
+                debug_assert!(node_key == parent_node.last_child_key.unwrap());
+                parent_node.first_child_key = None;
+                parent_node.last_child_key = None;
+            }
+        }
+    }
+
+    fn remove_from_arena<F>(&mut self, start_key_opt: Option<RSGNodeKey>, f: &mut F) where F: FnMut(CompLinksT) {
+        if start_key_opt.is_none() {
+            return;
+        }
+        let mut stk = smallvec::SmallVec::<[RSGNodeKey; 128]>::new();
+        stk.push(start_key_opt.unwrap());
+        while let Some(mut key) = stk.pop() {
+            loop {
+                let child_node = self.free_node(key);
+                f(child_node.comp_links);
+                if let Some(child_key) = child_node.first_child_key {
+                    stk.push(child_key);
+                }
+                match child_node.next_sibling_key {
+                    Some(sibling_key) => key = sibling_key,
+                    None => break
+                }
+            }
+        }
+    }
+
+    pub fn remove_children(&mut self, node_key: RSGNodeKey) -> smallvec::SmallVec<[CompLinksT; 16]> {
+        // NODE(A, B(C)) -> NODE
+        // Notifies: remove A, remove B
+
+        let mut component_links = smallvec::smallvec![];
+        let mut child_node_key_opt = self.arena[node_key].first_child_key;
+        while let Some(key) = child_node_key_opt {
+            child_node_key_opt = self.arena[key].next_sibling_key;
+            component_links.push(self.remove(key));
+        }
+        component_links
+    }
+
+    pub fn remove_children_with_callback<F>(&mut self, node_key: RSGNodeKey, mut f: F) where F: FnMut(CompLinksT) {
+        // NODE(A, B(C)) -> NODE
+        // f is called with the component links of A, B, C
+        // Notifies: remove A, remove B
+
+        let mut child_node_key_opt = self.arena[node_key].first_child_key;
+        while let Some(key) = child_node_key_opt {
+            child_node_key_opt = self.arena[key].next_sibling_key;
+            self.remove_helper(key, true, &mut f);
+        }
+    }
+
+    pub fn clear(&mut self) -> smallvec::SmallVec<[CompLinksT; 16]> {
+        match self.root_key {
+            Some(key) => self.remove_children(key),
+            None => smallvec::smallvec![]
+        }
+    }
+
+    pub fn clear_with_callback<F>(&mut self, f: F) where F: FnMut(CompLinksT) {
+        if let Some(key) = self.root_key {
+            self.remove_children_with_callback(key, f);
+        }
+    }
+
+    pub fn insert_under(&mut self, parent_key: RSGNodeKey, node: RSGNode<CompLinksT>) -> RSGNodeKey {
+        // A(B, C(D)) -> A(NODE(B, C(D)))
+        // Notifies: detach B, detach C, add NODE
+
+        debug_assert!(node.is_clean() && self.is_valid(parent_key));
 
         let mut child_node_key_opt = self.arena[parent_key].first_child_key;
         while let Some(key) = child_node_key_opt {
-            self.notify(RSGEvent::SubtreeAboutToBeTemporarilyDetached(key));
+            self.notify_detaching(key, self.placement_of(key));
             child_node_key_opt = self.arena[key].next_sibling_key;
         }
-        child_node_key_opt = self.arena[parent_key].first_child_key;
 
         let node_key = self.arena.insert(node);
+        self.insert_under_impl(parent_key, node_key);
+        self.notify_added(node_key);
+
+        node_key
+    }
+
+    fn insert_under_impl(&mut self, parent_key: RSGNodeKey, node_key: RSGNodeKey) {
+        let mut child_node_key_opt = self.arena[parent_key].first_child_key;
         let mut first_child_key_opt: Option<RSGNodeKey> = Some(node_key);
         let mut last_child_key_opt: Option<RSGNodeKey> = Some(node_key);
         {
@@ -552,10 +1374,6 @@ impl<CompLinksT, ObserverT> RSGScene<CompLinksT, ObserverT> where CompLinksT: De
             self.arena.get_mut(key).unwrap().parent_key = Some(node_key);
             child_node_key_opt = self.arena[key].next_sibling_key;
         }
-
-        self.notify(RSGEvent::SubtreeAddedOrReattached(node_key));
-
-        node_key
     }
 
     pub fn remove_without_children(&mut self, node_key: RSGNodeKey) -> CompLinksT {
@@ -568,12 +1386,12 @@ impl<CompLinksT, ObserverT> RSGScene<CompLinksT, ObserverT> where CompLinksT: De
 
         let mut child_node_key_opt = self.arena[node_key].first_child_key;
         while let Some(key) = child_node_key_opt {
-            self.notify(RSGEvent::SubtreeAboutToBeTemporarilyDetached(key));
+            self.notify_detaching(key, self.placement_of(key));
             child_node_key_opt = self.arena[key].next_sibling_key;
         }
         child_node_key_opt = self.arena[node_key].first_child_key;
 
-        let component_links = self.remove_helper(node_key, false);
+        let component_links = self.remove_helper(node_key, false, &mut |_| {});
 
         while let Some(key) = child_node_key_opt {
             child_node_key_opt = self.arena[key].next_sibling_key;
@@ -581,13 +1399,100 @@ impl<CompLinksT, ObserverT> RSGScene<CompLinksT, ObserverT> where CompLinksT: De
                 Some(before_key) => self.insert_before_impl(before_key, key),
                 None => self.append_impl(parent_key, key)
             }
-            self.notify(RSGEvent::SubtreeAddedOrReattached(key));
+            self.notify_added(key);
         }
 
         component_links
     }
 
-    pub fn traverse(&self, node_key: RSGNodeKey) -> RSGIter<CompLinksT, ObserverT> {
+    fn child_keys(&self, parent_key: RSGNodeKey) -> smallvec::SmallVec<[RSGNodeKey; 16]> {
+        self.children(parent_key).collect()
+    }
+
+    fn reorder_children_impl(&mut self, parent_key: RSGNodeKey, child_keys: &[RSGNodeKey]) {
+        let old_child_keys = self.child_keys(parent_key);
+        debug_assert!(old_child_keys.len() == child_keys.len());
+        if old_child_keys.as_slice() == child_keys {
+            return;
+        }
+        {
+            let parent_node = self.arena.get_mut(parent_key).unwrap();
+            parent_node.first_child_key = child_keys.first().copied();
+            parent_node.last_child_key = child_keys.last().copied();
+        }
+        for (idx, key) in child_keys.iter().enumerate() {
+            let node = self.arena.get_mut(*key).unwrap();
+            debug_assert!(node.parent_key == Some(parent_key));
+            node.prev_sibling_key = if idx > 0 { Some(child_keys[idx - 1]) } else { None };
+            node.next_sibling_key = child_keys.get(idx + 1).copied();
+        }
+        self.notify(RSGEvent::ChildrenReordered(parent_key));
+        if let Some(obs) = self.hierarchy_observer.as_mut() {
+            obs.notify_hierarchy(RSGHierarchyEvent::SiblingOrderChanged(parent_key, child_keys));
+        }
+    }
+
+    pub fn move_to_index(&mut self, node_key: RSGNodeKey, index: usize) {
+        // A(B, NODE, C, D) -> A(B, C, NODE, D) if index == 2
+        // (an index past the end moves NODE to the end)
+        // Notifies: reorder A
+
+        assert!(node_key != self.root_key.unwrap());
+        debug_assert!(self.is_valid(node_key));
+        let parent_key = self.arena[node_key].parent_key.unwrap();
+        let mut child_keys = self.child_keys(parent_key);
+        let old_index = child_keys.iter().position(|key| *key == node_key).unwrap();
+        child_keys.remove(old_index);
+        child_keys.insert(index.min(child_keys.len()), node_key);
+        self.reorder_children_impl(parent_key, &child_keys);
+    }
+
+    pub fn swap_siblings(&mut self, a_key: RSGNodeKey, b_key: RSGNodeKey) {
+        // P(A, B, C) -> P(C, B, A) if a_key == A.key and b_key == C.key
+        // Notifies: reorder P
+
+        assert!(a_key != self.root_key.unwrap() && b_key != self.root_key.unwrap());
+        debug_assert!(self.is_valid(a_key) && self.is_valid(b_key));
+        let parent_key = self.arena[a_key].parent_key.unwrap();
+        assert!(self.arena[b_key].parent_key == Some(parent_key));
+        let mut child_keys = self.child_keys(parent_key);
+        let a_index = child_keys.iter().position(|key| *key == a_key).unwrap();
+        let b_index = child_keys.iter().position(|key| *key == b_key).unwrap();
+        child_keys.swap(a_index, b_index);
+        self.reorder_children_impl(parent_key, &child_keys);
+    }
+
+    pub fn raise_to_front(&mut self, node_key: RSGNodeKey) {
+        // A(NODE, B, C) -> A(B, C, NODE)
+        // (last child is the frontmost)
+        // Notifies: reorder A
+
+        self.move_to_index(node_key, usize::MAX);
+    }
+
+    pub fn lower_to_back(&mut self, node_key: RSGNodeKey) {
+        // A(B, C, NODE) -> A(NODE, B, C)
+        // Notifies: reorder A
+
+        self.move_to_index(node_key, 0);
+    }
+
+    pub fn sort_children_by<K, F>(&mut self, parent_key: RSGNodeKey, mut key_fn: F)
+        where K: Ord, F: FnMut(&RSGNode<CompLinksT>) -> K
+    {
+        // P(A, B, C) -> P(B, C, A) if key_fn orders them that way
+        // (stable)
+        // Notifies: reorder P, if the order changed
+
+        debug_assert!(self.is_valid(parent_key));
+        let mut keyed_children: smallvec::SmallVec<[(K, RSGNodeKey); 16]> = self.child_keys(parent_key).iter()
+            .map(|key| (key_fn(&self.arena[*key]), *key)).collect();
+        keyed_children.sort_by(|a, b| a.0.cmp(&b.0));
+        let child_keys: smallvec::SmallVec<[RSGNodeKey; 16]> = keyed_children.into_iter().map(|(_, key)| key).collect();
+        self.reorder_children_impl(parent_key, &child_keys);
+    }
+
+    pub fn traverse(&self, node_key: RSGNodeKey) -> RSGIter<'_, CompLinksT, ObserverT> {
         // depth-first, pre-order
         RSGIter {
             scene: self,
@@ -596,7 +1501,47 @@ impl<CompLinksT, ObserverT> RSGScene<CompLinksT, ObserverT> where CompLinksT: De
         }
     }
 
-    pub fn ancestors(&self, node_key: RSGNodeKey) -> RSGAncestorIter<CompLinksT, ObserverT> {
+    pub fn visit<F>(&self, node_key: RSGNodeKey, mut f: F) where F: FnMut(RSGNodeKey, u32) -> RSGVisitResult {
+        // depth-first, pre-order, with the callback controlling the descent
+        let mut it = self.traverse(node_key);
+        while let Some((key, depth)) = it.next() {
+            match f(key, depth) {
+                RSGVisitResult::Continue => {},
+                RSGVisitResult::SkipChildren => it.skip_children(),
+                RSGVisitResult::Stop => break
+            }
+        }
+    }
+
+    pub fn traverse_breadth_first(&self, node_key: RSGNodeKey) -> RSGBreadthFirstIter<'_, CompLinksT, ObserverT> {
+        // breadth-first, level order
+        let mut queue = std::collections::VecDeque::new();
+        queue.push_back((node_key, 0));
+        RSGBreadthFirstIter {
+            scene: self,
+            queue
+        }
+    }
+
+    pub fn traverse_post_order(&self, node_key: RSGNodeKey) -> RSGPostOrderIter<'_, CompLinksT, ObserverT> {
+        // depth-first, post-order (children before parent)
+        RSGPostOrderIter {
+            scene: self,
+            start_key: node_key,
+            next: Some(RSGPostOrderIter::first_leaf(self, node_key, 0))
+        }
+    }
+
+    pub fn traverse_reverse(&self, node_key: RSGNodeKey) -> RSGReverseIter<'_, CompLinksT, ObserverT> {
+        // depth-first, reverse pre-order (exactly the reverse of traverse())
+        RSGReverseIter {
+            scene: self,
+            start_key: node_key,
+            next: Some(RSGReverseIter::last_leaf(self, node_key, 0))
+        }
+    }
+
+    pub fn ancestors(&self, node_key: RSGNodeKey) -> RSGAncestorIter<'_, CompLinksT, ObserverT> {
         // ancestors only
         RSGAncestorIter {
             scene: self,
@@ -604,7 +1549,7 @@ impl<CompLinksT, ObserverT> RSGScene<CompLinksT, ObserverT> where CompLinksT: De
         }
     }
 
-    pub fn ancestors_with_node(&self, node_key: RSGNodeKey) -> RSGAncestorIter<CompLinksT, ObserverT> {
+    pub fn ancestors_with_node(&self, node_key: RSGNodeKey) -> RSGAncestorIter<'_, CompLinksT, ObserverT> {
         // node and its ancestors
         RSGAncestorIter {
             scene: self,
@@ -612,17 +1557,554 @@ impl<CompLinksT, ObserverT> RSGScene<CompLinksT, ObserverT> where CompLinksT: De
         }
     }
 
-    pub fn iter(&self) -> slotmap::Iter<RSGNodeKey, RSGNode<CompLinksT>> {
+    pub fn children(&self, node_key: RSGNodeKey) -> RSGChildIter<'_, CompLinksT, ObserverT> {
+        // direct children, first to last
+        RSGChildIter {
+            scene: self,
+            next: self[node_key].first_child_key
+        }
+    }
+
+    pub fn children_rev(&self, node_key: RSGNodeKey) -> RSGChildRevIter<'_, CompLinksT, ObserverT> {
+        // direct children, last to first
+        RSGChildRevIter {
+            scene: self,
+            next: self[node_key].last_child_key
+        }
+    }
+
+    pub fn siblings(&self, node_key: RSGNodeKey) -> RSGSiblingIter<'_, CompLinksT, ObserverT> {
+        // other children of the parent, first to last (none for the root)
+        let first_key = self[node_key].parent_key.and_then(|parent_key| self[parent_key].first_child_key);
+        RSGSiblingIter {
+            children: RSGChildIter {
+                scene: self,
+                next: first_key
+            },
+            node_key
+        }
+    }
+
+    pub fn child_count(&self, node_key: RSGNodeKey) -> usize {
+        self.children(node_key).count()
+    }
+
+    pub fn nth_child(&self, node_key: RSGNodeKey, index: usize) -> Option<RSGNodeKey> {
+        self.children(node_key).nth(index)
+    }
+
+    pub fn depth(&self, node_key: RSGNodeKey) -> u32 {
+        // 0 for the root (or the root of a detached subtree)
+        self.ancestors(node_key).count() as u32
+    }
+
+    pub fn is_ancestor_of(&self, ancestor_key: RSGNodeKey, node_key: RSGNodeKey) -> bool {
+        // false when ancestor_key == node_key
+        self.ancestors(node_key).any(|key| key == ancestor_key)
+    }
+
+    pub fn lowest_common_ancestor(&self, a_key: RSGNodeKey, b_key: RSGNodeKey) -> Option<RSGNodeKey> {
+        // a_key itself if it is an ancestor of b_key (or the same node),
+        // None if the nodes are not in the same tree
+        let a_depth = self.depth(a_key);
+        let b_depth = self.depth(b_key);
+        let mut a = self.ancestors_with_node(a_key).skip(a_depth.saturating_sub(b_depth) as usize);
+        let mut b = self.ancestors_with_node(b_key).skip(b_depth.saturating_sub(a_depth) as usize);
+        loop {
+            match (a.next(), b.next()) {
+                (Some(a_key), Some(b_key)) if a_key == b_key => return Some(a_key),
+                (Some(_), Some(_)) => continue,
+                _ => return None
+            }
+        }
+    }
+
+    pub fn path_between(&self, from_key: RSGNodeKey, to_key: RSGNodeKey) -> Option<smallvec::SmallVec<[RSGNodeKey; 16]>> {
+        // A(B(C), D(E)): C -> E gives C, B, A, D, E
+        // (both ends and the lowest common ancestor included, None if not in the same tree)
+        let lca_key = self.lowest_common_ancestor(from_key, to_key)?;
+        let mut path: smallvec::SmallVec<[RSGNodeKey; 16]> = self.ancestors_with_node(from_key)
+            .take_while(|key| *key != lca_key).collect();
+        path.push(lca_key);
+        let down_start = path.len();
+        path.extend(self.ancestors_with_node(to_key).take_while(|key| *key != lca_key));
+        path[down_start..].reverse();
+        Some(path)
+    }
+
+    pub fn index_in_parent(&self, node_key: RSGNodeKey) -> Option<usize> {
+        let parent_key = self[node_key].parent_key?;
+        self.children(parent_key).position(|key| key == node_key)
+    }
+
+    pub fn first_child(&self, node_key: RSGNodeKey) -> Option<RSGNodeKey> {
+        self[node_key].first_child_key
+    }
+
+    pub fn last_child(&self, node_key: RSGNodeKey) -> Option<RSGNodeKey> {
+        self[node_key].last_child_key
+    }
+
+    pub fn next_sibling(&self, node_key: RSGNodeKey) -> Option<RSGNodeKey> {
+        self[node_key].next_sibling_key
+    }
+
+    pub fn prev_sibling(&self, node_key: RSGNodeKey) -> Option<RSGNodeKey> {
+        self[node_key].prev_sibling_key
+    }
+
+    pub fn iter(&self) -> slotmap::Iter<'_, RSGNodeKey, RSGNode<CompLinksT>> {
         self.arena.iter()
     }
 
-    pub fn iter_mut(&mut self) -> slotmap::IterMut<RSGNodeKey, RSGNode<CompLinksT>> {
+    pub fn iter_mut(&mut self) -> slotmap::IterMut<'_, RSGNodeKey, RSGNode<CompLinksT>> {
         self.arena.iter_mut()
     }
 
     pub fn mark_dirty(&mut self, node_key: RSGNodeKey, flags: RSGDirtyFlags) {
         self.notify(RSGEvent::Dirty(node_key, flags));
     }
+
+    // Names are optional and not unique. Paths are made of names separated by '/',
+    // unnamed nodes are referred to by their index in the parent: "#2". Absolute
+    // paths start with '/' followed by the root, e.g. "/root/ui/toolbar/button3".
+    // Besides names, path segments may be "." and "..", globs with '*' and '?'
+    // ("button*"), or "**" to match any number of levels.
+
+    pub fn set_name(&mut self, node_key: RSGNodeKey, name: &str) {
+        assert!(is_valid_name(name));
+        debug_assert!(self.arena.contains_key(node_key));
+        self.clear_name(node_key);
+        self.names.insert(node_key, name.to_owned());
+        self.name_index.entry(name.to_owned()).or_default().push(node_key);
+    }
+
+    pub fn clear_name(&mut self, node_key: RSGNodeKey) {
+        if let Some(name) = self.names.remove(node_key) {
+            let keys = self.name_index.get_mut(&name).unwrap();
+            keys.retain(|key| *key != node_key);
+            if keys.is_empty() {
+                self.name_index.remove(&name);
+            }
+        }
+    }
+
+    pub fn name(&self, node_key: RSGNodeKey) -> Option<&str> {
+        self.names.get(node_key).map(|name| name.as_str())
+    }
+
+    pub fn find_by_name(&self, name: &str) -> smallvec::SmallVec<[RSGNodeKey; 4]> {
+        // nodes in the scene with the given name, not in any particular order
+        match self.name_index.get(name) {
+            Some(keys) => keys.iter().copied().filter(|key| self.is_reachable(*key)).collect(),
+            None => smallvec::smallvec![]
+        }
+    }
+
+    fn path_segment(&self, node_key: RSGNodeKey) -> String {
+        match self.name(node_key) {
+            Some(name) => name.to_owned(),
+            None => format!("#{}", self.index_in_parent(node_key).unwrap_or(0))
+        }
+    }
+
+    pub fn path_of(&self, node_key: RSGNodeKey) -> String {
+        // absolute path, resolves back to node_key with find_by_path() as long as
+        // the names along the path are unique among their siblings
+        let mut segments: Vec<String> = self.ancestors_with_node(node_key).map(|key| self.path_segment(key)).collect();
+        segments.reverse();
+        format!("/{}", segments.join("/"))
+    }
+
+    pub fn find_by_path(&self, path: &str) -> Vec<RSGNodeKey> {
+        // relative paths are relative to the root
+        match self.root_key {
+            Some(root_key) => self.find_by_path_from(root_key, path),
+            None => vec![]
+        }
+    }
+
+    pub fn find_by_path_from(&self, node_key: RSGNodeKey, path: &str) -> Vec<RSGNodeKey> {
+        // all matching nodes, in the order of the path segments matching them
+        let mut segments = path.split('/').filter(|segment| !segment.is_empty()).peekable();
+        let mut matches: Vec<RSGNodeKey> = vec![];
+        if path.starts_with('/') {
+            // the first segment matches the root itself
+            match (self.root_key, segments.next()) {
+                (Some(root_key), Some(segment)) => {
+                    if segment == "**" {
+                        matches.extend(self.traverse(root_key).map(|(key, _)| key));
+                    } else if self.segment_matches(root_key, segment) {
+                        matches.push(root_key);
+                    }
+                }
+                (Some(root_key), None) => matches.push(root_key),
+                (None, _) => ()
+            }
+        } else if self.is_valid(node_key) {
+            matches.push(node_key);
+        }
+
+        for segment in segments {
+            let mut seen: std::collections::HashSet<RSGNodeKey> = std::collections::HashSet::new();
+            let mut next_matches: Vec<RSGNodeKey> = vec![];
+            for key in matches {
+                match segment {
+                    "." => next_matches.push(key),
+                    ".." => next_matches.extend(self.arena[key].parent_key),
+                    "**" => next_matches.extend(self.traverse(key).map(|(key, _)| key)),
+                    _ => next_matches.extend(self.children(key).filter(|child_key| self.segment_matches(*child_key, segment)))
+                }
+            }
+            next_matches.retain(|key| seen.insert(*key));
+            matches = next_matches;
+        }
+        matches
+    }
+
+    fn segment_matches(&self, node_key: RSGNodeKey, segment: &str) -> bool {
+        if let Some(index) = segment.strip_prefix('#') {
+            return index.parse::<usize>().ok() == self.index_in_parent(node_key).or(Some(0));
+        }
+        match self.name(node_key) {
+            Some(name) => {
+                let pattern: smallvec::SmallVec<[char; 32]> = segment.chars().collect();
+                let text: smallvec::SmallVec<[char; 32]> = name.chars().collect();
+                glob_matches(&pattern, &text)
+            }
+            None => segment == "*"
+        }
+    }
+
+    // Fallible variants: these validate their arguments and return an error instead
+    // of panicking (or, with debug assertions disabled, corrupting the scene), so
+    // that keys coming from untrusted code can be passed in directly.
+
+    fn check_valid(&self, node_key: RSGNodeKey) -> RSGSceneResult<()> {
+        // nodes of a detached subtree are still in the arena but not in the
+        // scene, reported separately from removed (or pending) ones
+        if self.is_valid(node_key) {
+            Ok(())
+        } else if self.arena.contains_key(node_key) && self.ancestors_with_node(node_key).last().is_some_and(|key| self.is_detached(key)) {
+            Err(RSGSceneError::DetachedNode(node_key))
+        } else {
+            Err(RSGSceneError::InvalidNode(node_key))
+        }
+    }
+
+    fn check_not_root(&self, node_key: RSGNodeKey) -> RSGSceneResult<()> {
+        self.check_valid(node_key)?;
+        if self.root_key == Some(node_key) {
+            Err(RSGSceneError::RootNotAllowed(node_key))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn check_clean(node: &RSGNode<CompLinksT>) -> RSGSceneResult<()> {
+        if node.is_clean() {
+            Ok(())
+        } else {
+            Err(RSGSceneError::NodeNotClean)
+        }
+    }
+
+    fn check_position(&self, parent_key: RSGNodeKey, position: RSGNodePosition) -> RSGSceneResult<()> {
+        self.check_valid(parent_key)?;
+        if let RSGNodePosition::Before(sibling_key) | RSGNodePosition::After(sibling_key) = position {
+            self.check_not_root(sibling_key)?;
+            if self.arena[sibling_key].parent_key != Some(parent_key) {
+                return Err(RSGSceneError::NotAChild(sibling_key, parent_key));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn try_set_root(&mut self, node: RSGNode<CompLinksT>) -> RSGSceneResult<RSGNodeKey> {
+        if self.root_key.is_some() {
+            return Err(RSGSceneError::RootAlreadySet);
+        }
+        Self::check_clean(&node)?;
+        Ok(self.set_root(node))
+    }
+
+    pub fn try_root(&self) -> RSGSceneResult<RSGNodeKey> {
+        self.root_key.ok_or(RSGSceneError::NoRoot)
+    }
+
+    pub fn try_get(&self, node_key: RSGNodeKey) -> RSGSceneResult<&RSGNode<CompLinksT>> {
+        self.check_valid(node_key)?;
+        Ok(&self.arena[node_key])
+    }
+
+    pub fn try_get_component_links(&self, node_key: RSGNodeKey) -> RSGSceneResult<&CompLinksT> {
+        self.check_valid(node_key)?;
+        Ok(self.get_component_links(node_key))
+    }
+
+    pub fn try_get_component_links_mut(&mut self, node_key: RSGNodeKey) -> RSGSceneResult<&mut CompLinksT> {
+        self.check_valid(node_key)?;
+        Ok(self.get_component_links_mut(node_key))
+    }
+
+    pub fn try_append(&mut self, parent_key: RSGNodeKey, node: RSGNode<CompLinksT>) -> RSGSceneResult<RSGNodeKey> {
+        self.check_valid(parent_key)?;
+        Self::check_clean(&node)?;
+        Ok(self.append(parent_key, node))
+    }
+
+    pub fn try_prepend(&mut self, parent_key: RSGNodeKey, node: RSGNode<CompLinksT>) -> RSGSceneResult<RSGNodeKey> {
+        self.check_valid(parent_key)?;
+        Self::check_clean(&node)?;
+        Ok(self.prepend(parent_key, node))
+    }
+
+    pub fn try_insert_before(&mut self, before_key: RSGNodeKey, node: RSGNode<CompLinksT>) -> RSGSceneResult<RSGNodeKey> {
+        self.check_not_root(before_key)?;
+        Self::check_clean(&node)?;
+        Ok(self.insert_before(before_key, node))
+    }
+
+    pub fn try_insert_after(&mut self, after_key: RSGNodeKey, node: RSGNode<CompLinksT>) -> RSGSceneResult<RSGNodeKey> {
+        self.check_not_root(after_key)?;
+        Self::check_clean(&node)?;
+        Ok(self.insert_after(after_key, node))
+    }
+
+    pub fn try_insert(&mut self, parent_key: RSGNodeKey, node: RSGNode<CompLinksT>, position: RSGNodePosition) -> RSGSceneResult<RSGNodeKey> {
+        self.check_position(parent_key, position)?;
+        Self::check_clean(&node)?;
+        Ok(match position {
+            RSGNodePosition::Append => self.append(parent_key, node),
+            RSGNodePosition::Prepend => self.prepend(parent_key, node),
+            RSGNodePosition::Before(before_key) => self.insert_before(before_key, node),
+            RSGNodePosition::After(after_key) => self.insert_after(after_key, node)
+        })
+    }
+
+    pub fn try_insert_under(&mut self, parent_key: RSGNodeKey, node: RSGNode<CompLinksT>) -> RSGSceneResult<RSGNodeKey> {
+        self.check_valid(parent_key)?;
+        Self::check_clean(&node)?;
+        Ok(self.insert_under(parent_key, node))
+    }
+
+    pub fn try_move_subtree(&mut self, node_key: RSGNodeKey, new_parent_key: RSGNodeKey, position: RSGNodePosition) -> RSGSceneResult<()> {
+        self.check_not_root(node_key)?;
+        self.check_position(new_parent_key, position)?;
+        if self.ancestors_with_node(new_parent_key).any(|key| key == node_key) {
+            return Err(RSGSceneError::MoveIntoOwnSubtree(node_key, new_parent_key));
+        }
+        if position == RSGNodePosition::Before(node_key) || position == RSGNodePosition::After(node_key) {
+            return Err(RSGSceneError::NotAChild(node_key, new_parent_key));
+        }
+        self.move_subtree(node_key, new_parent_key, position);
+        Ok(())
+    }
+
+    pub fn try_remove(&mut self, node_key: RSGNodeKey) -> RSGSceneResult<CompLinksT> {
+        self.check_not_root(node_key)?;
+        Ok(self.remove(node_key))
+    }
+
+    pub fn try_remove_with_callback<F>(&mut self, node_key: RSGNodeKey, f: F) -> RSGSceneResult<()> where F: FnMut(CompLinksT) {
+        self.check_not_root(node_key)?;
+        self.remove_with_callback(node_key, f);
+        Ok(())
+    }
+
+    pub fn try_remove_children(&mut self, node_key: RSGNodeKey) -> RSGSceneResult<smallvec::SmallVec<[CompLinksT; 16]>> {
+        self.check_valid(node_key)?;
+        Ok(self.remove_children(node_key))
+    }
+
+    pub fn try_remove_without_children(&mut self, node_key: RSGNodeKey) -> RSGSceneResult<CompLinksT> {
+        self.check_not_root(node_key)?;
+        Ok(self.remove_without_children(node_key))
+    }
+
+    pub fn try_detach(&mut self, node_key: RSGNodeKey) -> RSGSceneResult<RSGDetachedSubtree> {
+        self.check_not_root(node_key)?;
+        Ok(self.detach(node_key))
+    }
+
+    pub fn try_reattach(&mut self, detached: RSGDetachedSubtree, parent_key: RSGNodeKey, position: RSGNodePosition)
+        -> Result<RSGNodeKey, (RSGSceneError, RSGDetachedSubtree)>
+    {
+        // on error the detached subtree is handed back
+        if !self.is_detached(detached.node_key) {
+            return Err((RSGSceneError::InvalidNode(detached.node_key), detached));
+        }
+        if let Err(e) = self.check_position(parent_key, position) {
+            return Err((e, detached));
+        }
+        if self.ancestors_with_node(parent_key).any(|key| key == detached.node_key) {
+            return Err((RSGSceneError::MoveIntoOwnSubtree(detached.node_key, parent_key), detached));
+        }
+        Ok(self.reattach(detached, parent_key, position))
+    }
+
+    pub fn try_move_to_index(&mut self, node_key: RSGNodeKey, index: usize) -> RSGSceneResult<()> {
+        self.check_not_root(node_key)?;
+        self.move_to_index(node_key, index);
+        Ok(())
+    }
+
+    pub fn try_swap_siblings(&mut self, a_key: RSGNodeKey, b_key: RSGNodeKey) -> RSGSceneResult<()> {
+        self.check_not_root(a_key)?;
+        self.check_not_root(b_key)?;
+        let parent_key = self.arena[a_key].parent_key.unwrap();
+        if self.arena[b_key].parent_key != Some(parent_key) {
+            return Err(RSGSceneError::NotAChild(b_key, parent_key));
+        }
+        self.swap_siblings(a_key, b_key);
+        Ok(())
+    }
+
+    pub fn try_mark_dirty(&mut self, node_key: RSGNodeKey, flags: RSGDirtyFlags) -> RSGSceneResult<()> {
+        self.check_valid(node_key)?;
+        self.mark_dirty(node_key, flags);
+        Ok(())
+    }
+
+    pub fn try_set_name(&mut self, node_key: RSGNodeKey, name: &str) -> RSGSceneResult<()> {
+        // like set_name(), nodes in detached subtrees can be named too
+        if !self.arena.contains_key(node_key) {
+            return Err(RSGSceneError::InvalidNode(node_key));
+        }
+        if !is_valid_name(name) {
+            return Err(RSGSceneError::InvalidName);
+        }
+        self.set_name(node_key, name);
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<RSGSceneViolation>> {
+        // Checks the links of every node in the arena. Besides the root, nodes without
+        // a parent are only allowed as the roots of detached subtrees (see detach(),
+        // also used for removals kept for undo) or when not yet added (pending in a
+        // RSGSubtreeAddTransaction). Any other parentless node is an orphan, reported
+        // as Unreachable together with its subtree.
+
+        let mut violations = vec![];
+        let mut free_root_keys: smallvec::SmallVec<[RSGNodeKey; 16]> = smallvec::smallvec![];
+        for (key, node) in self.arena.iter() {
+            let is_root = self.root_key == Some(key);
+            let is_pending = node.key.is_none() && node.is_clean();
+            if node.key != Some(key) && !is_pending {
+                violations.push(RSGSceneViolation::KeyMismatch(key, node.key));
+            }
+            if is_root && (node.parent_key.is_some() || node.prev_sibling_key.is_some() || node.next_sibling_key.is_some()) {
+                violations.push(RSGSceneViolation::RootLinked(key));
+            }
+            if !is_root && node.parent_key.is_none() && (node.detached || is_pending) {
+                free_root_keys.push(key);
+            }
+            let links = [
+                (RSGNodeLink::Parent, node.parent_key),
+                (RSGNodeLink::FirstChild, node.first_child_key),
+                (RSGNodeLink::LastChild, node.last_child_key),
+                (RSGNodeLink::PrevSibling, node.prev_sibling_key),
+                (RSGNodeLink::NextSibling, node.next_sibling_key)
+            ];
+            for (link, target_key) in links.iter() {
+                if let Some(target_key) = target_key {
+                    if !self.arena.contains_key(*target_key) {
+                        violations.push(RSGSceneViolation::DanglingLink(key, *link, *target_key));
+                    }
+                }
+            }
+        }
+        if !violations.is_empty() {
+            // the rest would follow dangling links
+            return Err(violations);
+        }
+
+        // walk the child lists from the root and the free nodes, every node must be visited exactly once
+        let mut visited: std::collections::HashSet<RSGNodeKey> = std::collections::HashSet::new();
+        let mut stack: Vec<RSGNodeKey> = vec![];
+        for start_key in self.root_key.iter().chain(free_root_keys.iter()) {
+            if !visited.insert(*start_key) {
+                continue;
+            }
+            stack.push(*start_key);
+            while let Some(parent_key) = stack.pop() {
+                let parent_node = &self.arena[parent_key];
+                let mut prev_key_opt: Option<RSGNodeKey> = None;
+                let mut child_key_opt = parent_node.first_child_key;
+                while let Some(child_key) = child_key_opt {
+                    if !visited.insert(child_key) {
+                        violations.push(RSGSceneViolation::Cycle(child_key));
+                        break;
+                    }
+                    let child_node = &self.arena[child_key];
+                    if child_node.parent_key != Some(parent_key) {
+                        violations.push(RSGSceneViolation::ParentMismatch(child_key, parent_key));
+                    }
+                    if child_node.prev_sibling_key != prev_key_opt {
+                        violations.push(RSGSceneViolation::PrevSiblingMismatch(child_key, prev_key_opt));
+                    }
+                    stack.push(child_key);
+                    prev_key_opt = Some(child_key);
+                    child_key_opt = child_node.next_sibling_key;
+                }
+                if child_key_opt.is_none() && parent_node.last_child_key != prev_key_opt {
+                    violations.push(RSGSceneViolation::LastChildMismatch(parent_key));
+                }
+            }
+        }
+        for key in self.arena.keys() {
+            if !visited.contains(&key) {
+                violations.push(RSGSceneViolation::Unreachable(key));
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains('/') && !name.starts_with('#') && name != "." && name != ".."
+}
+
+fn glob_matches(pattern: &[char], text: &[char]) -> bool {
+    // '*' matches any number of characters, '?' exactly one. Iterative, on a
+    // mismatch only the last '*' is retried with one more character, which is
+    // enough since an earlier '*' could not match anything the last one cannot.
+    let mut p = 0;
+    let mut t = 0;
+    // (position of the last '*' in pattern, position in text it is retried from)
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    star = Some((star_p, star_t + 1));
+                    p = star_p + 1;
+                    t = star_t + 1;
+                }
+                None => return false
+            }
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+impl<CompLinksT, ObserverT> Default for RSGScene<CompLinksT, ObserverT> where CompLinksT: Default + Copy, ObserverT: RSGObserver {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<CompLinksT, ObserverT> std::ops::Index<RSGNodeKey> for RSGScene<CompLinksT, ObserverT>
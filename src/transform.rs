@@ -13,7 +13,7 @@ pub struct RSGTransformComponent {
 impl RSGTransformComponent {
     pub fn new(local_transform: glm::Mat4) -> Self {
         RSGTransformComponent {
-            local_transform: local_transform,
+            local_transform,
             world_transform: local_transform
         }
     }
@@ -0,0 +1,348 @@
+use crate::scene::*;
+use crate::components::*;
+use crate::material::*;
+use crate::mesh::*;
+use nalgebra_glm as glm;
+
+// Removed (and undone added) subtrees are only unlinked from the scene, so that
+// their keys and components stay valid for redo/undo. Observers see them as
+// temporarily detached. They get destroyed, without further notification, when
+// the step referring to them is discarded.
+
+enum RSGUndoCommand {
+    Add {
+        node_key: RSGNodeKey,
+        parent_key: RSGNodeKey,
+        position: RSGNodePosition
+    },
+    Remove {
+        node_key: RSGNodeKey,
+        parent_key: RSGNodeKey,
+        position: RSGNodePosition
+    },
+    Move {
+        node_key: RSGNodeKey,
+        old_parent_key: RSGNodeKey,
+        old_position: RSGNodePosition,
+        new_parent_key: RSGNodeKey,
+        new_position: RSGNodePosition
+    },
+    LocalTransform {
+        node_key: RSGNodeKey,
+        old_value: glm::Mat4,
+        new_value: glm::Mat4
+    },
+    Opacity {
+        node_key: RSGNodeKey,
+        old_value: f32,
+        new_value: f32
+    },
+    MaterialPropertyValue {
+        node_key: RSGNodeKey,
+        name: String,
+        old_value: Option<RSGMaterialPropertyValue>,
+        new_value: Option<RSGMaterialPropertyValue>
+    },
+    Mesh {
+        node_key: RSGNodeKey,
+        old_value: Box<RSGMesh>,
+        new_value: Box<RSGMesh>
+    }
+}
+
+struct RSGUndoStep {
+    name: String,
+    commands: Vec<RSGUndoCommand>
+}
+
+#[derive(Default)]
+pub struct RSGUndoStack {
+    steps: Vec<RSGUndoStep>,
+    undo_count: usize,
+    open_step: Option<RSGUndoStep>
+}
+
+fn set_local_transform<ObserverT>(components: &mut RSGComponentContainer, scene: &mut RSGScene<RSGComponentLinks, ObserverT>,
+    node_key: RSGNodeKey, value: glm::Mat4)
+    where ObserverT: RSGObserver
+{
+    let transform_key = scene.get_component_links(node_key).transform_key.unwrap();
+    components.transforms[transform_key].local_transform = value;
+    scene.mark_dirty(node_key, RSGDirtyFlags::TRANSFORM);
+}
+
+fn set_opacity<ObserverT>(components: &mut RSGComponentContainer, scene: &mut RSGScene<RSGComponentLinks, ObserverT>,
+    node_key: RSGNodeKey, value: f32)
+    where ObserverT: RSGObserver
+{
+    let opacity_key = scene.get_component_links(node_key).opacity_key.unwrap();
+    components.opacities[opacity_key].opacity = value;
+    scene.mark_dirty(node_key, RSGDirtyFlags::OPACITY);
+}
+
+fn set_material_property_value<ObserverT>(components: &mut RSGComponentContainer, scene: &mut RSGScene<RSGComponentLinks, ObserverT>,
+    node_key: RSGNodeKey, name: &str, value: Option<RSGMaterialPropertyValue>) -> Option<RSGMaterialPropertyValue>
+    where ObserverT: RSGObserver
+{
+    let material_key = scene.get_component_links(node_key).material_key.unwrap();
+    let property_values = &mut components.material_data[material_key].property_values;
+    let old_value = match value {
+        Some(v) => property_values.insert(name.to_owned(), v),
+        None => property_values.remove(name)
+    };
+    scene.mark_dirty(node_key, RSGDirtyFlags::MATERIAL_VALUES);
+    old_value
+}
+
+fn set_mesh<ObserverT>(components: &mut RSGComponentContainer, scene: &mut RSGScene<RSGComponentLinks, ObserverT>,
+    node_key: RSGNodeKey, value: RSGMesh) -> RSGMesh
+    where ObserverT: RSGObserver
+{
+    let mesh_key = scene.get_component_links(node_key).mesh_key.unwrap();
+    let old_value = std::mem::replace(&mut components.mesh_data[mesh_key], value);
+    scene.mark_dirty(node_key, RSGDirtyFlags::MESH);
+    old_value
+}
+
+impl RSGUndoCommand {
+    fn undo<ObserverT>(&self, components: &mut RSGComponentContainer, scene: &mut RSGScene<RSGComponentLinks, ObserverT>)
+        where ObserverT: RSGObserver
+    {
+        match self {
+            RSGUndoCommand::Add { node_key, .. } => scene.unlink_subtree(*node_key),
+            RSGUndoCommand::Remove { node_key, parent_key, position } => scene.link_subtree(*node_key, *parent_key, *position),
+            RSGUndoCommand::Move { node_key, old_parent_key, old_position, .. } => scene.move_subtree(*node_key, *old_parent_key, *old_position),
+            RSGUndoCommand::LocalTransform { node_key, old_value, .. } => set_local_transform(components, scene, *node_key, *old_value),
+            RSGUndoCommand::Opacity { node_key, old_value, .. } => set_opacity(components, scene, *node_key, *old_value),
+            RSGUndoCommand::MaterialPropertyValue { node_key, name, old_value, .. } => {
+                set_material_property_value(components, scene, *node_key, name, *old_value);
+            }
+            RSGUndoCommand::Mesh { node_key, old_value, .. } => {
+                set_mesh(components, scene, *node_key, (**old_value).clone());
+            }
+        }
+    }
+
+    fn redo<ObserverT>(&self, components: &mut RSGComponentContainer, scene: &mut RSGScene<RSGComponentLinks, ObserverT>)
+        where ObserverT: RSGObserver
+    {
+        match self {
+            RSGUndoCommand::Add { node_key, parent_key, position } => scene.link_subtree(*node_key, *parent_key, *position),
+            RSGUndoCommand::Remove { node_key, .. } => scene.unlink_subtree(*node_key),
+            RSGUndoCommand::Move { node_key, new_parent_key, new_position, .. } => scene.move_subtree(*node_key, *new_parent_key, *new_position),
+            RSGUndoCommand::LocalTransform { node_key, new_value, .. } => set_local_transform(components, scene, *node_key, *new_value),
+            RSGUndoCommand::Opacity { node_key, new_value, .. } => set_opacity(components, scene, *node_key, *new_value),
+            RSGUndoCommand::MaterialPropertyValue { node_key, name, new_value, .. } => {
+                set_material_property_value(components, scene, *node_key, name, *new_value);
+            }
+            RSGUndoCommand::Mesh { node_key, new_value, .. } => {
+                set_mesh(components, scene, *node_key, (**new_value).clone());
+            }
+        }
+    }
+
+    fn discard<ObserverT>(&self, components: &mut RSGComponentContainer, scene: &mut RSGScene<RSGComponentLinks, ObserverT>)
+        where ObserverT: RSGObserver
+    {
+        // destroy the subtree if this command is what keeps it unlinked
+        match self {
+            RSGUndoCommand::Add { node_key, .. } | RSGUndoCommand::Remove { node_key, .. }
+                if scene.is_detached(*node_key) =>
+            {
+                scene.remove_unlinked_subtree(*node_key, |component_links| components.remove(component_links));
+            }
+            _ => {}
+        }
+    }
+}
+
+impl RSGUndoStack {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.undo_count > 0
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.undo_count < self.steps.len()
+    }
+
+    pub fn undo_name(&self) -> Option<&str> {
+        if self.can_undo() {
+            Some(&self.steps[self.undo_count - 1].name)
+        } else {
+            None
+        }
+    }
+
+    pub fn redo_name(&self) -> Option<&str> {
+        if self.can_redo() {
+            Some(&self.steps[self.undo_count].name)
+        } else {
+            None
+        }
+    }
+
+    pub fn begin_step(&mut self, name: &str) {
+        assert!(self.open_step.is_none());
+        self.open_step = Some(RSGUndoStep {
+            name: name.to_owned(),
+            commands: vec![]
+        });
+    }
+
+    pub fn end_step<ObserverT>(&mut self, components: &mut RSGComponentContainer, scene: &mut RSGScene<RSGComponentLinks, ObserverT>)
+        where ObserverT: RSGObserver
+    {
+        let step = self.open_step.take().unwrap();
+        if !step.commands.is_empty() {
+            self.push_step(components, scene, step);
+        }
+    }
+
+    fn push_step<ObserverT>(&mut self, components: &mut RSGComponentContainer, scene: &mut RSGScene<RSGComponentLinks, ObserverT>,
+        step: RSGUndoStep)
+        where ObserverT: RSGObserver
+    {
+        for discarded_step in self.steps.drain(self.undo_count..) {
+            for command in discarded_step.commands.iter().rev() {
+                command.discard(components, scene);
+            }
+        }
+        self.steps.push(step);
+        self.undo_count = self.steps.len();
+    }
+
+    fn record<ObserverT>(&mut self, components: &mut RSGComponentContainer, scene: &mut RSGScene<RSGComponentLinks, ObserverT>,
+        name: &str, command: RSGUndoCommand)
+        where ObserverT: RSGObserver
+    {
+        match self.open_step.as_mut() {
+            Some(step) => step.commands.push(command),
+            None => self.push_step(components, scene, RSGUndoStep {
+                name: name.to_owned(),
+                commands: vec![command]
+            })
+        }
+    }
+
+    pub fn insert<ObserverT>(&mut self, components: &mut RSGComponentContainer, scene: &mut RSGScene<RSGComponentLinks, ObserverT>,
+        parent_key: RSGNodeKey, node: RSGNode<RSGComponentLinks>, position: RSGNodePosition) -> RSGNodeKey
+        where ObserverT: RSGObserver
+    {
+        let node_key = match position {
+            RSGNodePosition::Append => scene.append(parent_key, node),
+            RSGNodePosition::Prepend => scene.prepend(parent_key, node),
+            RSGNodePosition::Before(before_key) => scene.insert_before(before_key, node),
+            RSGNodePosition::After(after_key) => scene.insert_after(after_key, node)
+        };
+        let position = scene.position_of(node_key);
+        self.record(components, scene, "Add", RSGUndoCommand::Add { node_key, parent_key, position });
+        node_key
+    }
+
+    pub fn remove<ObserverT>(&mut self, components: &mut RSGComponentContainer, scene: &mut RSGScene<RSGComponentLinks, ObserverT>,
+        node_key: RSGNodeKey)
+        where ObserverT: RSGObserver
+    {
+        let parent_key = scene[node_key].parent_key.unwrap();
+        let position = scene.position_of(node_key);
+        scene.unlink_subtree(node_key);
+        self.record(components, scene, "Remove", RSGUndoCommand::Remove { node_key, parent_key, position });
+    }
+
+    pub fn move_subtree<ObserverT>(&mut self, components: &mut RSGComponentContainer, scene: &mut RSGScene<RSGComponentLinks, ObserverT>,
+        node_key: RSGNodeKey, new_parent_key: RSGNodeKey, position: RSGNodePosition)
+        where ObserverT: RSGObserver
+    {
+        let old_parent_key = scene[node_key].parent_key.unwrap();
+        let old_position = scene.position_of(node_key);
+        scene.move_subtree(node_key, new_parent_key, position);
+        let new_position = scene.position_of(node_key);
+        self.record(components, scene, "Move", RSGUndoCommand::Move {
+            node_key, old_parent_key, old_position, new_parent_key, new_position
+        });
+    }
+
+    pub fn set_local_transform<ObserverT>(&mut self, components: &mut RSGComponentContainer, scene: &mut RSGScene<RSGComponentLinks, ObserverT>,
+        node_key: RSGNodeKey, local_transform: glm::Mat4)
+        where ObserverT: RSGObserver
+    {
+        let old_value = components.transforms[scene.get_component_links(node_key).transform_key.unwrap()].local_transform;
+        set_local_transform(components, scene, node_key, local_transform);
+        self.record(components, scene, "Transform", RSGUndoCommand::LocalTransform { node_key, old_value, new_value: local_transform });
+    }
+
+    pub fn set_opacity<ObserverT>(&mut self, components: &mut RSGComponentContainer, scene: &mut RSGScene<RSGComponentLinks, ObserverT>,
+        node_key: RSGNodeKey, opacity: f32)
+        where ObserverT: RSGObserver
+    {
+        let old_value = components.opacities[scene.get_component_links(node_key).opacity_key.unwrap()].opacity;
+        set_opacity(components, scene, node_key, opacity);
+        self.record(components, scene, "Opacity", RSGUndoCommand::Opacity { node_key, old_value, new_value: opacity });
+    }
+
+    pub fn set_material_property_value<ObserverT>(&mut self, components: &mut RSGComponentContainer, scene: &mut RSGScene<RSGComponentLinks, ObserverT>,
+        node_key: RSGNodeKey, name: &str, value: Option<RSGMaterialPropertyValue>)
+        where ObserverT: RSGObserver
+    {
+        // None removes the value
+        let old_value = set_material_property_value(components, scene, node_key, name, value);
+        self.record(components, scene, "Material", RSGUndoCommand::MaterialPropertyValue {
+            node_key, name: name.to_owned(), old_value, new_value: value
+        });
+    }
+
+    pub fn set_mesh<ObserverT>(&mut self, components: &mut RSGComponentContainer, scene: &mut RSGScene<RSGComponentLinks, ObserverT>,
+        node_key: RSGNodeKey, mesh: RSGMesh)
+        where ObserverT: RSGObserver
+    {
+        let old_value = set_mesh(components, scene, node_key, mesh.clone());
+        self.record(components, scene, "Mesh", RSGUndoCommand::Mesh {
+            node_key, old_value: Box::new(old_value), new_value: Box::new(mesh)
+        });
+    }
+
+    pub fn undo<ObserverT>(&mut self, components: &mut RSGComponentContainer, scene: &mut RSGScene<RSGComponentLinks, ObserverT>) -> bool
+        where ObserverT: RSGObserver
+    {
+        debug_assert!(self.open_step.is_none());
+        if !self.can_undo() {
+            return false;
+        }
+        self.undo_count -= 1;
+        for command in self.steps[self.undo_count].commands.iter().rev() {
+            command.undo(components, scene);
+        }
+        true
+    }
+
+    pub fn redo<ObserverT>(&mut self, components: &mut RSGComponentContainer, scene: &mut RSGScene<RSGComponentLinks, ObserverT>) -> bool
+        where ObserverT: RSGObserver
+    {
+        debug_assert!(self.open_step.is_none());
+        if !self.can_redo() {
+            return false;
+        }
+        for command in self.steps[self.undo_count].commands.iter() {
+            command.redo(components, scene);
+        }
+        self.undo_count += 1;
+        true
+    }
+
+    pub fn clear<ObserverT>(&mut self, components: &mut RSGComponentContainer, scene: &mut RSGScene<RSGComponentLinks, ObserverT>)
+        where ObserverT: RSGObserver
+    {
+        // destroys everything kept alive only for undo/redo
+        debug_assert!(self.open_step.is_none());
+        for step in self.steps.drain(..).rev() {
+            for command in step.commands.iter().rev() {
+                command.discard(components, scene);
+            }
+        }
+        self.undo_count = 0;
+    }
+}
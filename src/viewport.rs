@@ -12,17 +12,41 @@ pub struct RSGViewportRect {
     pub h: u32
 }
 
+impl RSGViewportRect {
+    pub fn new(x: u32, y: u32, w: u32, h: u32) -> Self {
+        RSGViewportRect { x, y, w, h }
+    }
+
+    pub fn relative_to(&self, parent: &RSGViewportRect) -> Self {
+        // offset by the parent's origin and clipped to the parent, saturating
+        // at the end of the u32 range
+        let right = parent.x.saturating_add(parent.w);
+        let bottom = parent.y.saturating_add(parent.h);
+        let x = parent.x.saturating_add(self.x).min(right);
+        let y = parent.y.saturating_add(self.y).min(bottom);
+        RSGViewportRect {
+            x,
+            y,
+            w: self.w.min(right - x),
+            h: self.h.min(bottom - y)
+        }
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct RSGViewportComponent {
     pub rect: Option<RSGViewportRect>,
-    pub camera_node_key: Option<RSGNodeKey>
+    pub camera_node_key: Option<RSGNodeKey>,
+    // drop renderables outside the camera's view frustum
+    pub culling: bool
 }
 
 impl RSGViewportComponent {
     pub fn new(rect: Option<RSGViewportRect>, camera_node_key: Option<RSGNodeKey>) -> Self {
         RSGViewportComponent {
-            rect: rect,
-            camera_node_key: camera_node_key
+            rect,
+            camera_node_key,
+            culling: true
         }
     }
 }
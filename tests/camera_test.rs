@@ -0,0 +1,142 @@
+#![allow(clippy::assertions_on_constants)]
+
+use rsg::camera::*;
+use rsg::mesh::*;
+use nalgebra_glm as glm;
+
+fn make_box(center: glm::Vec3, half_size: f32) -> RSGAabb {
+    RSGAabb {
+        minimum: center - glm::vec3(half_size, half_size, half_size),
+        maximum: center + glm::vec3(half_size, half_size, half_size)
+    }
+}
+
+#[test]
+fn perspective_frustum() {
+    let camera = RSGCamera::Perspective(RSGPerspectiveProjection {
+        aspect_ratio: 1.0,
+        fov: 90.0,
+        near: 1.0,
+        far: 100.0
+    });
+    let frustum = RSGFrustum::new(&camera, &glm::one());
+    assert!(frustum.intersects_aabb(&make_box(glm::vec3(0.0, 0.0, -10.0), 1.0)));
+    // behind the camera, before near, and beyond far
+    assert!(!frustum.intersects_aabb(&make_box(glm::vec3(0.0, 0.0, 10.0), 1.0)));
+    assert!(!frustum.intersects_aabb(&make_box(glm::vec3(0.0, 0.0, -0.5), 0.1)));
+    assert!(!frustum.intersects_aabb(&make_box(glm::vec3(0.0, 0.0, -200.0), 1.0)));
+    // the sides are at 45 degrees
+    assert!(!frustum.intersects_aabb(&make_box(glm::vec3(13.0, 0.0, -10.0), 1.0)));
+    assert!(frustum.intersects_aabb(&make_box(glm::vec3(10.5, 0.0, -10.0), 1.0)));
+    assert!(!frustum.intersects_aabb(&make_box(glm::vec3(0.0, -13.0, -10.0), 1.0)));
+    assert!(frustum.intersects_aabb(&make_box(glm::vec3(0.0, -10.5, -10.0), 1.0)));
+
+    // camera at (0, 0, 50), turned around to look towards +Z
+    let world_transform = glm::translation(&glm::vec3(0.0, 0.0, 50.0)) * glm::rotation(std::f32::consts::PI, &glm::vec3(0.0, 1.0, 0.0));
+    let frustum = RSGFrustum::new(&camera, &world_transform);
+    assert!(!frustum.intersects_aabb(&make_box(glm::vec3(0.0, 0.0, 40.0), 1.0)));
+    assert!(frustum.intersects_aabb(&make_box(glm::vec3(0.0, 0.0, 60.0), 1.0)));
+    assert!(frustum.intersects_aabb(&make_box(glm::vec3(-9.5, 0.0, 60.0), 1.0)));
+    assert!(!frustum.intersects_aabb(&make_box(glm::vec3(-13.0, 0.0, 60.0), 1.0)));
+}
+
+#[test]
+fn orthographic_frustum() {
+    let camera = RSGCamera::Orthographic(RSGOrthographicProjection {
+        xmag: 10.0,
+        ymag: 5.0,
+        near: 0.0,
+        far: 100.0
+    });
+    let frustum = RSGFrustum::new(&camera, &glm::translation(&glm::vec3(100.0, 0.0, 0.0)));
+    assert!(frustum.intersects_aabb(&make_box(glm::vec3(100.0, 0.0, -50.0), 1.0)));
+    assert!(frustum.intersects_aabb(&make_box(glm::vec3(110.5, 5.5, -99.5), 1.0)));
+    assert!(!frustum.intersects_aabb(&make_box(glm::vec3(112.0, 0.0, -50.0), 1.0)));
+    assert!(!frustum.intersects_aabb(&make_box(glm::vec3(100.0, 7.0, -50.0), 1.0)));
+    assert!(!frustum.intersects_aabb(&make_box(glm::vec3(0.0, 0.0, -50.0), 1.0)));
+    assert!(!frustum.intersects_aabb(&make_box(glm::vec3(100.0, 0.0, 5.0), 1.0)));
+
+    // the camera component starts with an identity world transform
+    let component = RSGCameraComponent::new(camera);
+    assert!(component.frustum == RSGFrustum::new(&camera, &glm::one()));
+}
+
+#[test]
+fn transformed_aabb() {
+    let aabb = make_box(glm::vec3(1.0, 0.0, 0.0), 1.0);
+    let rotated = aabb.transformed(&glm::rotation(std::f32::consts::FRAC_PI_2, &glm::vec3(0.0, 0.0, 1.0)));
+    assert!(glm::distance(&rotated.minimum, &glm::vec3(-1.0, 0.0, -1.0)) < 1e-5);
+    assert!(glm::distance(&rotated.maximum, &glm::vec3(1.0, 2.0, 1.0)) < 1e-5);
+}
+
+fn project(m: &glm::Mat4, p: glm::Vec3) -> glm::Vec3 {
+    let clip = m * glm::vec4(p.x, p.y, p.z, 1.0);
+    glm::vec3(clip.x / clip.w, clip.y / clip.w, clip.z / clip.w)
+}
+
+fn near_equal(a: glm::Vec3, b: glm::Vec3) -> bool {
+    glm::distance(&a, &b) < 1e-4
+}
+
+#[test]
+fn projection_matrices() {
+    let perspective = RSGCamera::Perspective(RSGPerspectiveProjection {
+        aspect_ratio: 2.0,
+        fov: 90.0,
+        near: 1.0,
+        far: 100.0
+    });
+    // top right corner on the near plane, and the center of the far plane
+    let near_corner = glm::vec3(2.0, 1.0, -1.0);
+    let far_center = glm::vec3(0.0, 0.0, -100.0);
+
+    let m = perspective.projection_matrix(RSGClipSpace::opengl());
+    assert!(near_equal(project(&m, near_corner), glm::vec3(1.0, 1.0, -1.0)));
+    assert!(near_equal(project(&m, far_center), glm::vec3(0.0, 0.0, 1.0)));
+    let m = perspective.projection_matrix(RSGClipSpace::direct3d());
+    assert!(near_equal(project(&m, near_corner), glm::vec3(1.0, 1.0, 0.0)));
+    assert!(near_equal(project(&m, far_center), glm::vec3(0.0, 0.0, 1.0)));
+    let m = perspective.projection_matrix(RSGClipSpace::vulkan());
+    assert!(near_equal(project(&m, near_corner), glm::vec3(1.0, -1.0, 0.0)));
+
+    let orthographic = RSGCamera::Orthographic(RSGOrthographicProjection {
+        xmag: 4.0,
+        ymag: 2.0,
+        near: 0.0,
+        far: 10.0
+    });
+    let m = orthographic.projection_matrix(RSGClipSpace::opengl());
+    assert!(near_equal(project(&m, glm::vec3(4.0, 2.0, 0.0)), glm::vec3(1.0, 1.0, -1.0)));
+    assert!(near_equal(project(&m, glm::vec3(-4.0, -2.0, -10.0)), glm::vec3(-1.0, -1.0, 1.0)));
+    let m = orthographic.projection_matrix(RSGClipSpace::vulkan());
+    assert!(near_equal(project(&m, glm::vec3(4.0, 2.0, -5.0)), glm::vec3(1.0, -1.0, 0.5)));
+
+    let mut camera = orthographic;
+    camera.set_aspect_ratio(3.0);
+    assert!(camera == RSGCamera::Orthographic(RSGOrthographicProjection { xmag: 6.0, ymag: 2.0, near: 0.0, far: 10.0 }));
+    assert!(camera.aspect_ratio() == Some(3.0) && perspective.aspect_ratio() == Some(2.0));
+    let flat = RSGCamera::Orthographic(RSGOrthographicProjection { xmag: 4.0, ymag: 0.0, near: 0.0, far: 10.0 });
+    assert!(flat.aspect_ratio().is_none());
+}
+
+#[test]
+fn camera_component_matrices() {
+    let mut component = RSGCameraComponent::new(RSGCamera::Perspective(RSGPerspectiveProjection {
+        aspect_ratio: 1.0,
+        fov: 90.0,
+        near: 1.0,
+        far: 100.0
+    }));
+    assert!(component.clip_space == RSGClipSpace::opengl());
+    assert!(component.matrices.view == glm::one::<glm::Mat4>());
+
+    let world_transform = glm::translation(&glm::vec3(0.0, 0.0, 10.0));
+    component.clip_space = RSGClipSpace::vulkan();
+    component.update_matrices(&world_transform);
+    assert!(component.matrices.inverse_view == world_transform);
+    assert!(near_equal(project(&component.matrices.view, glm::vec3(1.0, 2.0, 3.0)), glm::vec3(1.0, 2.0, -7.0)));
+    assert!(component.matrices.view_projection == component.matrices.projection * component.matrices.view);
+    assert!(component.matrices.projection == component.camera.projection_matrix(RSGClipSpace::vulkan()));
+    // a point 1 unit in front of the camera at its top edge ends up on the near plane, at the top (-1 with the flip)
+    assert!(near_equal(project(&component.matrices.view_projection, glm::vec3(0.0, 1.0, 9.0)), glm::vec3(0.0, -1.0, 0.0)));
+}
@@ -0,0 +1,810 @@
+#![allow(clippy::assertions_on_constants)]
+
+use rsg::scene::*;
+use rsg::components::*;
+use rsg::camera::*;
+use rsg::material::*;
+use rsg::mesh::*;
+use rsg::observer::*;
+use rsg::viewport::*;
+use nalgebra_glm as glm;
+
+struct TestObserver {
+    events: Vec<RSGEvent>
+}
+
+impl RSGObserver for TestObserver {
+    fn notify(&mut self, event: RSGEvent) {
+        self.events.push(event);
+    }
+}
+
+impl TestObserver {
+    fn new() -> Self {
+        TestObserver {
+            events: vec![]
+        }
+    }
+}
+
+type TestScene = RSGScene::<RSGComponentLinks, TestObserver>;
+
+fn make_material() -> RSGMaterial {
+    let mut material = RSGMaterial {
+        shader_set_id: 1,
+        property_values: Default::default(),
+        graphics_state: Default::default()
+    };
+    material.property_values.insert("color".to_owned(), RSGMaterialPropertyValue::Custom(RSGMaterialCustomValue::Vec3(glm::vec3(1.0, 0.0, 0.0))));
+    material
+}
+
+fn make_mesh() -> RSGMesh {
+    RSGMesh {
+        vertex_views: smallvec::smallvec![],
+        submeshes: smallvec::smallvec![],
+        bounds: RSGAabb {
+            minimum: glm::vec3(-1.0, -1.0, 0.0),
+            maximum: glm::vec3(1.0, 1.0, 0.0)
+        }
+    }
+}
+
+fn make_camera() -> RSGCamera {
+    RSGCamera::Perspective(RSGPerspectiveProjection {
+        aspect_ratio: 16.0 / 9.0,
+        fov: 45.0,
+        near: 0.01,
+        far: 1000.0
+    })
+}
+
+fn prepare(scene: &mut RSGScene<RSGComponentLinks, RSGSceneObserver>, components: &mut RSGComponentContainer,
+    render_lists: &mut RSGViewportRenderLists, work_list: &mut Vec<RSGNodeKey>, pool: &scoped_pool::Pool)
+{
+    // prepares the changes collected by the scene's observer since the previous call
+    let mut obs = scene.take_observer().unwrap();
+    prepare_scene(components, scene, &mut obs, render_lists, work_list, pool);
+    obs.reset();
+    scene.set_observer(obs);
+}
+
+#[test]
+fn clone_subtree() {
+    let mut scene = TestScene::new();
+    let mut components = RSGComponentContainer::default();
+    let root_key = components.add_default_root(&mut scene);
+
+    // ROOT(VP(CAM, MESH), OTHER)
+    let vp_key = scene.append(root_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components).viewport(None, None).links()));
+    let cam_key = scene.append(vp_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components)
+        .transform(glm::translation(&glm::vec3(0.0, 0.0, 600.0)))
+        .camera(make_camera())
+        .links()));
+    let mesh_key = scene.append(vp_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components)
+        .transform(glm::translation(&glm::vec3(1.0, 2.0, 3.0)))
+        .opacity(0.5)
+        .material(make_material())
+        .mesh(make_mesh())
+        .links()));
+    let other_key = scene.append(root_key, RSGNode::new());
+    components.viewports[scene.get_component_links(vp_key).viewport_key.unwrap()].camera_node_key = Some(cam_key);
+    assert!(scene.node_count() == 5);
+
+    scene.set_observer(TestObserver::new());
+
+    // ROOT(VP(CAM, MESH), VP'(CAM', MESH'), OTHER)
+    let vp_copy_key = components.clone_subtree(&mut scene, vp_key, root_key, RSGNodePosition::Before(other_key));
+    assert!(scene.node_count() == 8);
+    assert!(scene.is_valid(vp_copy_key));
+    assert!(components.transforms.len() == 5);
+    assert!(components.opacities.len() == 3);
+    assert!(components.materials.len() == 2 && components.material_data.len() == 2);
+    assert!(components.meshes.len() == 2 && components.mesh_data.len() == 2);
+    assert!(components.cameras.len() == 2);
+    assert!(components.viewports.len() == 2);
+
+    let obs = scene.take_observer().unwrap();
+    assert!(obs.events.len() == 1);
+    assert!(obs.events[0] == RSGEvent::SubtreeAddedOrReattached(vp_copy_key));
+
+    let copy: Vec<(RSGNodeKey, u32)> = scene.traverse(vp_copy_key).collect();
+    assert!(copy.len() == 3);
+    let cam_copy_key = copy[1].0;
+    let mesh_copy_key = copy[2].0;
+    assert!(cam_copy_key != cam_key && mesh_copy_key != mesh_key);
+    assert!(scene[vp_copy_key].parent_key == Some(root_key));
+    assert!(scene[other_key].links().4 == Some(vp_copy_key));
+
+    let links = *scene.get_component_links(mesh_key);
+    let copy_links = *scene.get_component_links(mesh_copy_key);
+    assert!(links.transform_key != copy_links.transform_key);
+    assert!(links.opacity_key != copy_links.opacity_key);
+    assert!(links.material_key != copy_links.material_key);
+    assert!(links.mesh_key != copy_links.mesh_key);
+    assert!(components.transforms[copy_links.transform_key.unwrap()].local_transform == glm::translation(&glm::vec3(1.0, 2.0, 3.0)));
+    assert!(components.opacities[copy_links.opacity_key.unwrap()].opacity == 0.5);
+    assert!(components.material_data[copy_links.material_key.unwrap()] == components.material_data[links.material_key.unwrap()]);
+    assert!(components.mesh_data[copy_links.mesh_key.unwrap()] == components.mesh_data[links.mesh_key.unwrap()]);
+
+    // the copied viewport uses the copied camera, the original is untouched
+    let vp_links = *scene.get_component_links(vp_key);
+    let vp_copy_links = *scene.get_component_links(vp_copy_key);
+    assert!(components.viewports[vp_links.viewport_key.unwrap()].camera_node_key == Some(cam_key));
+    assert!(components.viewports[vp_copy_links.viewport_key.unwrap()].camera_node_key == Some(cam_copy_key));
+
+    // changing the copy does not affect the original
+    components.opacities[copy_links.opacity_key.unwrap()].opacity = 0.25;
+    assert!(components.opacities[links.opacity_key.unwrap()].opacity == 0.5);
+}
+
+#[test]
+fn remove_subtree() {
+    let mut scene = TestScene::new();
+    let mut components = RSGComponentContainer::default();
+    let root_key = components.add_default_root(&mut scene);
+
+    // ROOT(NODE1(NODE11(MESH)), NODE2)
+    let node1_key = scene.append(root_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components).transform(glm::one()).opacity(1.0).links()));
+    let node11_key = scene.append(node1_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components).transform(glm::one()).links()));
+    scene.append(node11_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components)
+        .transform(glm::one())
+        .material(make_material())
+        .mesh(make_mesh())
+        .links()));
+    scene.append(root_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components).transform(glm::one()).links()));
+    assert!(scene.node_count() == 5);
+    assert!(components.transforms.len() == 5);
+
+    // ROOT(NODE2)
+    components.remove_subtree(&mut scene, node1_key);
+    assert!(scene.node_count() == 2);
+    assert!(components.transforms.len() == 2);
+    assert!(components.opacities.len() == 1);
+    assert!(components.materials.is_empty() && components.material_data.is_empty());
+    assert!(components.meshes.is_empty() && components.mesh_data.is_empty());
+}
+
+#[test]
+fn prepare_scene_skips_transparent_subtrees() {
+    let mut scene = RSGScene::<RSGComponentLinks, RSGSceneObserver>::new();
+    let mut components = RSGComponentContainer::default();
+    scene.set_observer(RSGSceneObserver::new());
+    let root_key = components.add_default_root(&mut scene);
+
+    // ROOT(CAM, VP(MESH1, HIDDEN(MESH2)))
+    let cam_key = scene.append(root_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components)
+        .transform(glm::translation(&glm::vec3(0.0, 0.0, 600.0)))
+        .camera(make_camera())
+        .links()));
+    let vp_key = scene.append(root_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components).viewport(None, Some(cam_key)).links()));
+    let mesh1_key = scene.append(vp_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components).transform(glm::one()).opacity(1.0).mesh(make_mesh()).links()));
+    let hidden_key = scene.append(vp_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components).transform(glm::one()).opacity(0.0).links()));
+    let mesh2_key = scene.append(hidden_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components).transform(glm::one()).opacity(1.0).mesh(make_mesh()).links()));
+
+    let pool = scoped_pool::Pool::new(2);
+    let mut render_lists = vec![];
+    let mut work_list = vec![];
+
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    assert!(render_lists.len() == 1 && render_lists[0].viewport_node_key == vp_key);
+    assert!(render_lists[0].opaque_list.len() == 1 && render_lists[0].opaque_list[0].0 == mesh1_key);
+    assert!(render_lists[0].alpha_list.is_empty());
+    // descendants of a fully transparent node are fully transparent as well
+    let mesh2_links = *scene.get_component_links(mesh2_key);
+    assert!(components.opacities[mesh2_links.opacity_key.unwrap()].inherited_opacity == 0.0);
+    assert!(!components.is_opaque(&mesh2_links));
+
+    // HIDDEN becomes visible, MESH2 is semi-transparent then
+    components.opacities[scene.get_component_links(hidden_key).opacity_key.unwrap()].opacity = 0.5;
+    scene.mark_dirty(hidden_key, RSGDirtyFlags::OPACITY);
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    assert!(render_lists[0].opaque_list.len() == 1 && render_lists[0].opaque_list[0].0 == mesh1_key);
+    assert!(render_lists[0].alpha_list.len() == 1 && render_lists[0].alpha_list[0].0 == mesh2_key);
+    assert!(components.opacities[scene.get_component_links(mesh2_key).opacity_key.unwrap()].inherited_opacity == 0.5);
+
+    // hiding it again does not leave the previous value in MESH2
+    components.opacities[scene.get_component_links(hidden_key).opacity_key.unwrap()].opacity = 0.0;
+    scene.mark_dirty(hidden_key, RSGDirtyFlags::OPACITY);
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    assert!(render_lists[0].alpha_list.is_empty());
+    assert!(components.opacities[scene.get_component_links(mesh2_key).opacity_key.unwrap()].inherited_opacity == 0.0);
+
+    // a detached viewport is not rendered, its components are kept for reattaching
+    let detached = scene.detach(vp_key);
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    assert!(render_lists.is_empty());
+    assert!(components.meshes.len() == 2);
+
+    components.remove_detached_subtree(&mut scene, detached);
+    assert!(scene.node_count() == 2);
+    assert!(components.meshes.is_empty() && components.viewports.is_empty());
+
+    pool.shutdown();
+}
+
+#[test]
+fn prepare_scene_propagates_world_transforms_and_opacities() {
+    let mut scene = RSGScene::<RSGComponentLinks, RSGSceneObserver>::new();
+    let mut components = RSGComponentContainer::default();
+    scene.set_observer(RSGSceneObserver::new());
+    let root_key = components.add_default_root(&mut scene);
+
+    // ROOT(A(GROUP(B(GROUP2(C)), D)), E)
+    // where the GROUP nodes have no components, so B inherits from A and C from B
+    let a_key = scene.append(root_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components).transform(glm::translation(&glm::vec3(1.0, 0.0, 0.0))).opacity(0.5).links()));
+    let group_key = scene.append(a_key, RSGNode::new());
+    let b_key = scene.append(group_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components).transform(glm::translation(&glm::vec3(0.0, 2.0, 0.0))).opacity(0.5).links()));
+    let group2_key = scene.append(b_key, RSGNode::new());
+    let c_key = scene.append(group2_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components).transform(glm::translation(&glm::vec3(0.0, 0.0, 3.0))).opacity(0.5).links()));
+    let d_key = scene.append(group_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components).transform(glm::one()).opacity(1.0).links()));
+    let e_key = scene.append(root_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components).transform(glm::one()).opacity(1.0).links()));
+
+    let pool = scoped_pool::Pool::new(2);
+    let mut render_lists = vec![];
+    let mut work_list = vec![];
+
+    type Scene = RSGScene<RSGComponentLinks, RSGSceneObserver>;
+    let world_pos = |components: &RSGComponentContainer, scene: &Scene, key| {
+        let m = components.transforms[scene.get_component_links(key).transform_key.unwrap()].world_transform;
+        glm::vec3(m[(0, 3)], m[(1, 3)], m[(2, 3)])
+    };
+    let inherited_opacity = |components: &RSGComponentContainer, scene: &Scene, key| {
+        components.opacities[scene.get_component_links(key).opacity_key.unwrap()].inherited_opacity
+    };
+
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    assert!(world_pos(&components, &scene, b_key) == glm::vec3(1.0, 2.0, 0.0));
+    assert!(world_pos(&components, &scene, c_key) == glm::vec3(1.0, 2.0, 3.0));
+    assert!(world_pos(&components, &scene, d_key) == glm::vec3(1.0, 0.0, 0.0));
+    assert!(world_pos(&components, &scene, e_key) == glm::vec3(0.0, 0.0, 0.0));
+    assert!(inherited_opacity(&components, &scene, b_key) == 0.25);
+    assert!(inherited_opacity(&components, &scene, c_key) == 0.125);
+    assert!(inherited_opacity(&components, &scene, d_key) == 0.5);
+    assert!(inherited_opacity(&components, &scene, e_key) == 1.0);
+
+    // dirty subtrees starting below a node without components pick up the
+    // values of the nearest ancestor with the component
+    components.transforms[scene.get_component_links(a_key).transform_key.unwrap()].local_transform = glm::translation(&glm::vec3(5.0, 0.0, 0.0));
+    components.transforms[scene.get_component_links(c_key).transform_key.unwrap()].local_transform = glm::translation(&glm::vec3(0.0, 0.0, 4.0));
+    components.opacities[scene.get_component_links(b_key).opacity_key.unwrap()].opacity = 1.0;
+    scene.mark_dirty(group2_key, RSGDirtyFlags::TRANSFORM);
+    scene.mark_dirty(group2_key, RSGDirtyFlags::OPACITY);
+    scene.mark_dirty(b_key, RSGDirtyFlags::OPACITY);
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    // A is not dirty, so C builds on the stale world transform of B
+    assert!(world_pos(&components, &scene, c_key) == glm::vec3(1.0, 2.0, 4.0));
+    assert!(world_pos(&components, &scene, b_key) == glm::vec3(1.0, 2.0, 0.0));
+    assert!(inherited_opacity(&components, &scene, b_key) == 0.5);
+    assert!(inherited_opacity(&components, &scene, c_key) == 0.25);
+
+    pool.shutdown();
+}
+
+#[test]
+fn prepare_scene_deep_chain() {
+    let mut scene = RSGScene::<RSGComponentLinks, RSGSceneObserver>::new();
+    let mut components = RSGComponentContainer::default();
+    scene.set_observer(RSGSceneObserver::new());
+    let root_key = scene.set_root(RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components).transform(glm::one()).opacity(1.0).links()));
+
+    // ROOT(GROUP(LEAF, GROUP(LEAF, GROUP(...)))) 50000 levels deep, where the
+    // groups have no components. Neither a recursive traversal (stack overflow)
+    // nor looking up the nearest ancestor with a component from each leaf
+    // (quadratic) would get through this.
+    let mut k = root_key;
+    let mut leaf_key = root_key;
+    let mut t = RSGSubtreeAddTransaction::new();
+    for _ in 0..50000 {
+        k = scene.append_with_transaction(k, RSGNode::new(), &mut t);
+        leaf_key = scene.append_with_transaction(k, RSGNode::with_component_links(
+            RSGComponentBuilder::new(&mut components).transform(glm::one()).opacity(0.5).links()),
+            &mut t);
+    }
+    scene.commit(t);
+
+    let pool = scoped_pool::Pool::new(2);
+    let mut render_lists = vec![];
+    let mut work_list = vec![];
+
+    let root_links = *scene.get_component_links(root_key);
+    components.transforms[root_links.transform_key.unwrap()].local_transform = glm::translation(&glm::vec3(1.0, 2.0, 0.0));
+    components.opacities[root_links.opacity_key.unwrap()].opacity = 0.5;
+    scene.mark_dirty(root_key, RSGDirtyFlags::TRANSFORM | RSGDirtyFlags::OPACITY);
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    let leaf_links = scene.get_component_links(leaf_key);
+    assert!(components.transforms[leaf_links.transform_key.unwrap()].world_transform == glm::translation(&glm::vec3(1.0, 2.0, 0.0)));
+    assert!(components.opacities[leaf_links.opacity_key.unwrap()].inherited_opacity == 0.25);
+
+    pool.shutdown();
+}
+
+#[test]
+fn prepare_scene_parallel_world_transforms() {
+    let mut scene = RSGScene::<RSGComponentLinks, RSGSceneObserver>::new();
+    let mut components = RSGComponentContainer::default();
+    let root_key = components.add_default_root(&mut scene);
+
+    // ROOT(GROUP0(N00(N01(...)), ...), ...) with 64 independent chains
+    let mut chain_roots = vec![];
+    let mut leaves = vec![];
+    for i in 0..64 {
+        let group_key = scene.append(root_key, RSGNode::new());
+        chain_roots.push(group_key);
+        let mut k = group_key;
+        for j in 0..20 {
+            k = scene.append(k, RSGNode::with_component_links(
+                RSGComponentBuilder::new(&mut components)
+                .transform(glm::rotation(0.01 * (i + j) as f32, &glm::vec3(0.0, 0.0, 1.0)) * glm::translation(&glm::vec3(i as f32, j as f32, 1.0)))
+                .links()));
+        }
+        leaves.push(k);
+    }
+
+    let pool = scoped_pool::Pool::new(4);
+    let mut render_lists = vec![];
+    let mut work_list = vec![];
+
+    // the chains are dirtied individually (the nested keys are dropped by normalize), so they are processed in parallel
+    let mut obs = RSGSceneObserver::new();
+    obs.dirty_world_roots.extend(chain_roots.iter().copied());
+    obs.dirty_world_roots.push(leaves[3]);
+    obs.dirty_world_roots.insert(0, scene.children(chain_roots[7]).next().unwrap());
+    obs.normalize(&scene);
+    assert!(obs.dirty_world_roots.as_slice() == chain_roots.as_slice());
+    prepare_scene(&mut components, &scene, &mut obs, &mut render_lists, &mut work_list, &pool);
+    let parallel: Vec<glm::Mat4> = components.transforms.values().map(|t| t.world_transform).collect();
+
+    // a single dirty root is processed on the calling thread
+    for t in components.transforms.values_mut() {
+        t.world_transform = glm::zero();
+    }
+    obs.reset();
+    obs.dirty_world_roots.push(root_key);
+    prepare_scene(&mut components, &scene, &mut obs, &mut render_lists, &mut work_list, &pool);
+    let serial: Vec<glm::Mat4> = components.transforms.values().map(|t| t.world_transform).collect();
+    assert!(parallel == serial);
+    assert!(serial.iter().all(|m| *m != glm::zero::<glm::Mat4>()));
+
+    pool.shutdown();
+}
+
+#[test]
+fn prepare_scene_nested_viewports() {
+    let mut scene = RSGScene::<RSGComponentLinks, RSGSceneObserver>::new();
+    let mut components = RSGComponentContainer::default();
+    scene.set_observer(RSGSceneObserver::new());
+    let root_key = components.add_default_root(&mut scene);
+
+    // ROOT(CAM, MAPCAM, VP(MESH1, MINIMAP(MESH2, NOCAM(MESH3)), MESH4))
+    let cam_key = scene.append(root_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components)
+        .transform(glm::translation(&glm::vec3(0.0, 0.0, 600.0)))
+        .camera(make_camera())
+        .links()));
+    let map_cam_key = scene.append(root_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components)
+        .transform(glm::translation(&glm::vec3(0.0, 0.0, 100.0)))
+        .camera(make_camera())
+        .links()));
+    let vp_key = scene.append(root_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components).viewport(Some(RSGViewportRect::new(0, 0, 800, 600)), Some(cam_key)).links()));
+    let make_renderable = |components: &mut RSGComponentContainer| RSGNode::with_component_links(
+        RSGComponentBuilder::new(components).transform(glm::one()).mesh(make_mesh()).links());
+    let mesh1_key = scene.append(vp_key, make_renderable(&mut components));
+    let minimap_key = scene.append(vp_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components).viewport(Some(RSGViewportRect::new(600, 10, 300, 100)), Some(map_cam_key)).links()));
+    let mesh2_key = scene.append(minimap_key, make_renderable(&mut components));
+    let nocam_key = scene.append(minimap_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components).viewport(None, None).links()));
+    let mesh3_key = scene.append(nocam_key, make_renderable(&mut components));
+    let mesh4_key = scene.append(vp_key, make_renderable(&mut components));
+
+    let pool = scoped_pool::Pool::new(2);
+    let mut render_lists = vec![];
+    let mut work_list = vec![];
+
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    type Scene = RSGScene<RSGComponentLinks, RSGSceneObserver>;
+    let viewport_of = |components: &RSGComponentContainer, scene: &Scene, key| {
+        components.meshes[scene.get_component_links(key).mesh_key.unwrap()].viewport_node_key
+    };
+    assert!(viewport_of(&components, &scene, mesh1_key) == Some(vp_key));
+    assert!(viewport_of(&components, &scene, mesh2_key) == Some(minimap_key));
+    assert!(viewport_of(&components, &scene, mesh3_key).is_none());
+    assert!(viewport_of(&components, &scene, mesh4_key) == Some(vp_key));
+    assert!(render_lists.iter().map(|r| r.viewport_node_key).collect::<Vec<_>>() == [vp_key, minimap_key, nocam_key]);
+    assert!(render_lists[0].camera_node_key == Some(cam_key) && render_lists[0].rect == Some(RSGViewportRect::new(0, 0, 800, 600)));
+    assert!(render_lists[0].opaque_list.len() == 2 && render_lists[0].alpha_list.is_empty());
+    assert!(render_lists[0].opaque_list.iter().all(|e| e.0 == mesh1_key || e.0 == mesh4_key));
+    assert!(render_lists[1].camera_node_key == Some(map_cam_key));
+    assert!(render_lists[1].opaque_list.len() == 1 && render_lists[1].opaque_list[0].0 == mesh2_key);
+    assert!(render_lists[2].camera_node_key.is_none() && render_lists[2].opaque_list.is_empty());
+
+    // the minimap is placed relative to, and clipped to, the outer viewport
+    let target_rect = RSGViewportRect::new(100, 50, 1920, 1080);
+    assert!(components.viewport_rect(&scene, vp_key, target_rect) == RSGViewportRect::new(100, 50, 800, 600));
+    assert!(components.viewport_rect(&scene, minimap_key, target_rect) == RSGViewportRect::new(700, 60, 200, 100));
+    assert!(components.viewport_rect(&scene, nocam_key, target_rect) == RSGViewportRect::new(700, 60, 200, 100));
+
+    // offsets near the end of the u32 range saturate instead of overflowing
+    let far_rect = RSGViewportRect::new(u32::MAX - 10, 0, 100, 100);
+    assert!(RSGViewportRect::new(20, 0, 5, 5).relative_to(&far_rect) == RSGViewportRect::new(u32::MAX, 0, 0, 5));
+    assert!(RSGViewportRect::new(5, 0, 50, 5).relative_to(&far_rect) == RSGViewportRect::new(u32::MAX - 5, 0, 5, 5));
+
+    // renderables follow their subtree to another viewport, removed ones are dropped
+    scene.move_subtree(mesh4_key, minimap_key, RSGNodePosition::Append);
+    scene.remove(mesh2_key);
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    assert!(viewport_of(&components, &scene, mesh4_key) == Some(minimap_key));
+    assert!(render_lists[0].opaque_list.len() == 1 && render_lists[0].opaque_list[0].0 == mesh1_key);
+    assert!(render_lists[1].opaque_list.len() == 1 && render_lists[1].opaque_list[0].0 == mesh4_key);
+
+    // a new viewport gets its lists in tree order, a removed one takes the nested ones with it
+    let vp0_key = scene.prepend(root_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components).viewport(None, Some(cam_key)).links()));
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    assert!(render_lists.iter().map(|r| r.viewport_node_key).collect::<Vec<_>>() == [vp0_key, vp_key, minimap_key, nocam_key]);
+    scene.remove(minimap_key);
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    assert!(render_lists.iter().map(|r| r.viewport_node_key).collect::<Vec<_>>() == [vp0_key, vp_key]);
+    assert!(render_lists[1].opaque_list.len() == 1 && render_lists[1].opaque_list[0].0 == mesh1_key);
+
+    pool.shutdown();
+}
+
+#[test]
+fn prepare_scene_updates_render_lists_incrementally() {
+    let mut scene = RSGScene::<RSGComponentLinks, RSGSceneObserver>::new();
+    let mut components = RSGComponentContainer::default();
+    scene.set_observer(RSGSceneObserver::new());
+    let root_key = components.add_default_root(&mut scene);
+
+    // ROOT(CAM, VP(MESH1, MESH2, MESH3))
+    let cam_key = scene.append(root_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components)
+        .transform(glm::translation(&glm::vec3(0.0, 0.0, 600.0)))
+        .camera(make_camera())
+        .links()));
+    let vp_key = scene.append(root_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components).viewport(None, Some(cam_key)).links()));
+    let mut mesh_keys = vec![];
+    for z in &[0.0, -10.0, -20.0] {
+        mesh_keys.push(scene.append(vp_key, RSGNode::with_component_links(
+            RSGComponentBuilder::new(&mut components)
+            .transform(glm::translation(&glm::vec3(0.0, 0.0, *z)))
+            .opacity(1.0)
+            .mesh(make_mesh())
+            .links())));
+    }
+    let (mesh1_key, mesh2_key, mesh3_key) = (mesh_keys[0], mesh_keys[1], mesh_keys[2]);
+
+    let pool = scoped_pool::Pool::new(2);
+    let mut render_lists = vec![];
+    let mut work_list = vec![];
+    let keys = |list: &RSGRenderList| list.iter().map(|e| e.0).collect::<Vec<_>>();
+
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    assert!(keys(&render_lists[0].opaque_list) == [mesh1_key, mesh2_key, mesh3_key]);
+    assert!(render_lists[0].alpha_list.is_empty());
+
+    // Moving MESH1 to the back only updates its distance and the order. The
+    // other entries are left as they are, which the bogus distance shows.
+    render_lists[0].opaque_list[1].1 = 611.0;
+    components.transforms[scene.get_component_links(mesh1_key).transform_key.unwrap()].local_transform = glm::translation(&glm::vec3(0.0, 0.0, -30.0));
+    scene.mark_dirty(mesh1_key, RSGDirtyFlags::TRANSFORM);
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    assert!(keys(&render_lists[0].opaque_list) == [mesh2_key, mesh3_key, mesh1_key]);
+    assert!(render_lists[0].opaque_list[2].1 == 630.0);
+    assert!(render_lists[0].opaque_list[0] == (mesh2_key, 611.0));
+
+    // semi-transparent MESH2 moves to the alpha list, keeping its distance
+    components.opacities[scene.get_component_links(mesh2_key).opacity_key.unwrap()].opacity = 0.5;
+    scene.mark_dirty(mesh2_key, RSGDirtyFlags::OPACITY);
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    assert!(keys(&render_lists[0].opaque_list) == [mesh3_key, mesh1_key]);
+    assert!(render_lists[0].alpha_list == [(mesh2_key, 611.0)]);
+
+    // moving the camera reverses the order (the direction stays the same)
+    components.transforms[scene.get_component_links(cam_key).transform_key.unwrap()].local_transform =
+        glm::rotation(std::f32::consts::PI, &glm::vec3(0.0, 1.0, 0.0)) * glm::translation(&glm::vec3(0.0, 0.0, 600.0));
+    scene.mark_dirty(cam_key, RSGDirtyFlags::TRANSFORM);
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    assert!(keys(&render_lists[0].opaque_list) == [mesh1_key, mesh3_key]);
+
+    // a fully transparent MESH3 is no longer a renderable, only its subtree is collected again
+    render_lists[0].alpha_list[0].1 = 1.0;
+    components.opacities[scene.get_component_links(mesh3_key).opacity_key.unwrap()].opacity = 0.0;
+    scene.mark_dirty(mesh3_key, RSGDirtyFlags::OPACITY);
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    assert!(keys(&render_lists[0].opaque_list) == [mesh1_key]);
+    assert!(render_lists[0].alpha_list == [(mesh2_key, 1.0)]);
+    assert!(components.meshes[scene.get_component_links(mesh3_key).mesh_key.unwrap()].viewport_node_key.is_none());
+
+    // a new renderable gets added to the lists of its viewport
+    let mesh4_key = scene.append(vp_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components).transform(glm::translation(&glm::vec3(0.0, 0.0, -5.0))).mesh(make_mesh()).links()));
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    assert!(keys(&render_lists[0].opaque_list) == [mesh1_key, mesh4_key]);
+    assert!(render_lists[0].alpha_list == [(mesh2_key, 1.0)]);
+    assert!(render_lists[0].material_values_changed.is_empty());
+
+    // changed material values are reported for the viewport
+    scene.mark_dirty(mesh1_key, RSGDirtyFlags::MATERIAL_VALUES);
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    assert!(render_lists[0].material_values_changed == [mesh1_key]);
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    assert!(render_lists[0].material_values_changed.is_empty());
+
+    // the lists follow the viewport component even without it being marked dirty
+    let viewport_key = scene.get_component_links(vp_key).viewport_key.unwrap();
+    components.viewports[viewport_key].rect = Some(RSGViewportRect::new(0, 0, 100, 100));
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    assert!(render_lists[0].rect == Some(RSGViewportRect::new(0, 0, 100, 100)));
+    components.viewports[viewport_key].camera_node_key = None;
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    assert!(render_lists[0].camera_node_key.is_none() && render_lists[0].culling_stats() == RSGCullingStats::default());
+    assert!(components.meshes[scene.get_component_links(mesh1_key).mesh_key.unwrap()].viewport_node_key.is_none());
+
+    pool.shutdown();
+}
+
+#[test]
+fn prepare_scene_frustum_culling() {
+    let mut scene = RSGScene::<RSGComponentLinks, RSGSceneObserver>::new();
+    let mut components = RSGComponentContainer::default();
+    scene.set_observer(RSGSceneObserver::new());
+    let root_key = components.add_default_root(&mut scene);
+
+    // ROOT(CAM, VP(FRONT, BEHIND, ASIDE))
+    let cam_key = scene.append(root_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components)
+        .transform(glm::translation(&glm::vec3(0.0, 0.0, 10.0)))
+        .camera(make_camera())
+        .links()));
+    let vp_key = scene.append(root_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components).viewport(None, Some(cam_key)).links()));
+    let mut mesh_keys = vec![];
+    for pos in &[glm::vec3(0.0, 0.0, 0.0), glm::vec3(0.0, 0.0, 20.0), glm::vec3(100.0, 0.0, 0.0)] {
+        mesh_keys.push(scene.append(vp_key, RSGNode::with_component_links(
+            RSGComponentBuilder::new(&mut components).transform(glm::translation(pos)).mesh(make_mesh()).links())));
+    }
+    let (front_key, behind_key, aside_key) = (mesh_keys[0], mesh_keys[1], mesh_keys[2]);
+
+    let pool = scoped_pool::Pool::new(2);
+    let mut render_lists = vec![];
+    let mut work_list = vec![];
+
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    assert!(render_lists[0].opaque_list.len() == 1 && render_lists[0].opaque_list[0].0 == front_key);
+    assert!(render_lists[0].culled_list.len() == 2);
+    assert!(render_lists[0].culling_stats() == RSGCullingStats { visible: 1, culled: 2 });
+    // culled renderables still belong to the viewport
+    assert!(components.meshes[scene.get_component_links(aside_key).mesh_key.unwrap()].viewport_node_key == Some(vp_key));
+
+    // Only the moved renderables are culled again, unless the camera moves. The
+    // misplaced FRONT shows that it is left alone while ASIDE moves.
+    let front_entry = render_lists[0].opaque_list.remove(0);
+    render_lists[0].culled_list.push(front_entry.0);
+    components.transforms[scene.get_component_links(aside_key).transform_key.unwrap()].local_transform = glm::translation(&glm::vec3(200.0, 0.0, 0.0));
+    scene.mark_dirty(aside_key, RSGDirtyFlags::TRANSFORM);
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    assert!(render_lists[0].culling_stats() == RSGCullingStats { visible: 0, culled: 3 });
+    scene.mark_dirty(cam_key, RSGDirtyFlags::TRANSFORM);
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    assert!(render_lists[0].opaque_list == [front_entry]);
+    components.transforms[scene.get_component_links(aside_key).transform_key.unwrap()].local_transform = glm::translation(&glm::vec3(100.0, 0.0, 0.0));
+    scene.mark_dirty(aside_key, RSGDirtyFlags::TRANSFORM);
+
+    // BEHIND moves into view, FRONT moves out of it
+    components.transforms[scene.get_component_links(behind_key).transform_key.unwrap()].local_transform = glm::translation(&glm::vec3(0.0, 0.0, -5.0));
+    scene.mark_dirty(behind_key, RSGDirtyFlags::TRANSFORM);
+    components.transforms[scene.get_component_links(front_key).transform_key.unwrap()].local_transform = glm::translation(&glm::vec3(0.0, 0.0, 2000.0));
+    scene.mark_dirty(front_key, RSGDirtyFlags::TRANSFORM);
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    assert!(render_lists[0].opaque_list.len() == 1 && render_lists[0].opaque_list[0] == (behind_key, 15.0));
+    assert!(render_lists[0].culling_stats() == RSGCullingStats { visible: 1, culled: 2 });
+    assert!(render_lists[0].culled_list.contains(&front_key) && render_lists[0].culled_list.contains(&aside_key));
+
+    // turning the camera towards ASIDE
+    components.transforms[scene.get_component_links(cam_key).transform_key.unwrap()].local_transform =
+        glm::translation(&glm::vec3(0.0, 0.0, 10.0)) * glm::rotation(-std::f32::consts::FRAC_PI_2, &glm::vec3(0.0, 1.0, 0.0));
+    scene.mark_dirty(cam_key, RSGDirtyFlags::TRANSFORM);
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    assert!(render_lists[0].opaque_list.len() == 1 && render_lists[0].opaque_list[0].0 == aside_key);
+
+    // no culling for the viewport, the culled renderables go back to the lists
+    components.viewports[scene.get_component_links(vp_key).viewport_key.unwrap()].culling = false;
+    scene.mark_dirty(vp_key, RSGDirtyFlags::VIEWPORT);
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    assert!(render_lists[0].culling_stats() == RSGCullingStats { visible: 3, culled: 0 });
+    assert!(!render_lists[0].culling);
+
+    // and culling again (noticed from the component alone)
+    components.viewports[scene.get_component_links(vp_key).viewport_key.unwrap()].culling = true;
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    assert!(render_lists[0].culling_stats() == RSGCullingStats { visible: 1, culled: 2 });
+    assert!(render_lists[0].opaque_list[0].0 == aside_key);
+
+    pool.shutdown();
+}
+
+#[test]
+fn prepare_scene_camera_matrices() {
+    let mut scene = RSGScene::<RSGComponentLinks, RSGSceneObserver>::new();
+    let mut components = RSGComponentContainer::default();
+    scene.set_observer(RSGSceneObserver::new());
+    let root_key = components.add_default_root(&mut scene);
+
+    // ROOT(CAM, VP)
+    let cam_key = scene.append(root_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components)
+        .transform(glm::translation(&glm::vec3(0.0, 0.0, 10.0)))
+        .camera(make_camera())
+        .links()));
+    let camera_key = scene.get_component_links(cam_key).camera_key.unwrap();
+    components.cameras[camera_key].aspect_ratio_from_viewport = true;
+    let vp_key = scene.append(root_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components).viewport(Some(RSGViewportRect::new(0, 0, 400, 200)), Some(cam_key)).links()));
+
+    let pool = scoped_pool::Pool::new(2);
+    let mut render_lists = vec![];
+    let mut work_list = vec![];
+    let aspect_ratio = |components: &RSGComponentContainer| match components.cameras[camera_key].camera {
+        RSGCamera::Perspective(p) => p.aspect_ratio,
+        _ => 0.0
+    };
+
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    let camera = components.cameras[camera_key];
+    assert!(aspect_ratio(&components) == 2.0);
+    assert!(camera.matrices.inverse_view == glm::translation(&glm::vec3(0.0, 0.0, 10.0)));
+    assert!(camera.matrices.projection == camera.camera.projection_matrix(RSGClipSpace::opengl()));
+    assert!(camera.matrices.view_projection == camera.matrices.projection * camera.matrices.view);
+
+    // the viewport gets resized
+    components.viewports[scene.get_component_links(vp_key).viewport_key.unwrap()].rect = Some(RSGViewportRect::new(0, 0, 300, 300));
+    scene.mark_dirty(vp_key, RSGDirtyFlags::VIEWPORT);
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    assert!(aspect_ratio(&components) == 1.0);
+    assert!(components.cameras[camera_key].matrices.projection == components.cameras[camera_key].camera.projection_matrix(RSGClipSpace::opengl()));
+
+    // the camera moves, and switches to a different clip space
+    components.transforms[scene.get_component_links(cam_key).transform_key.unwrap()].local_transform = glm::translation(&glm::vec3(5.0, 0.0, 0.0));
+    scene.mark_dirty(cam_key, RSGDirtyFlags::TRANSFORM);
+    components.cameras[camera_key].clip_space = RSGClipSpace::vulkan();
+    scene.mark_dirty(cam_key, RSGDirtyFlags::CAMERA);
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    let camera = components.cameras[camera_key];
+    assert!(camera.matrices.view == glm::translation(&glm::vec3(-5.0, 0.0, 0.0)));
+    assert!(camera.matrices.projection == camera.camera.projection_matrix(RSGClipSpace::vulkan()));
+    assert!(camera.matrices.view_projection == camera.matrices.projection * camera.matrices.view);
+
+    // VP gets wider, and a mesh near its right edge. The aspect ratio is applied
+    // before culling, so the mesh is in view in the same prepare.
+    let vp_viewport_key = scene.get_component_links(vp_key).viewport_key.unwrap();
+    components.viewports[vp_viewport_key].rect = Some(RSGViewportRect::new(0, 0, 900, 300));
+    scene.append(vp_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components).transform(glm::translation(&glm::vec3(85.0, 0.0, -100.0))).mesh(make_mesh()).links()));
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    assert!(aspect_ratio(&components) == 3.0);
+    assert!(components.cameras[camera_key].matrices.inverse_view == glm::translation(&glm::vec3(5.0, 0.0, 0.0)));
+    assert!(render_lists[0].culling_stats() == RSGCullingStats { visible: 1, culled: 0 });
+
+    // another viewport using the camera does not change it, the first one decides
+    let vp2_key = scene.append(root_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components).viewport(Some(RSGViewportRect::new(0, 0, 100, 100)), Some(cam_key)).links()));
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    assert!(render_lists.len() == 2 && render_lists[1].viewport_node_key == vp2_key);
+    assert!(aspect_ratio(&components) == 3.0);
+    assert!(render_lists[0].culling_stats() == RSGCullingStats { visible: 1, culled: 0 });
+
+    // narrowing VP culls the mesh
+    components.viewports[vp_viewport_key].rect = Some(RSGViewportRect::new(0, 0, 300, 300));
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    assert!(aspect_ratio(&components) == 1.0);
+    assert!(render_lists[0].culling_stats() == RSGCullingStats { visible: 0, culled: 1 });
+
+    // a nested viewport is clipped to its parent, INNER(NESTED) with NESTED at
+    // (50, 0, 200, 100) uses only the 50x100 left within INNER, and comes first
+    let inner_key = scene.prepend(root_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components).viewport(Some(RSGViewportRect::new(0, 0, 100, 100)), None).links()));
+    let nested_key = scene.append(inner_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components).viewport(Some(RSGViewportRect::new(50, 0, 200, 100)), Some(cam_key)).links()));
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    assert!(aspect_ratio(&components) == 0.5);
+
+    // without a rect on the way up the target size decides, which is not known here
+    components.viewports[scene.get_component_links(inner_key).viewport_key.unwrap()].rect = None;
+    components.viewports[scene.get_component_links(nested_key).viewport_key.unwrap()].rect = None;
+    components.cameras[camera_key].camera.set_aspect_ratio(1.5);
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    assert!(aspect_ratio(&components) == 1.5);
+
+    // an orthographic camera without a ymag is left alone
+    components.viewports[scene.get_component_links(inner_key).viewport_key.unwrap()].rect = Some(RSGViewportRect::new(0, 0, 100, 100));
+    components.cameras[camera_key].camera = RSGCamera::Orthographic(RSGOrthographicProjection { xmag: 1.0, ymag: 0.0, near: 0.0, far: 10.0 });
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    assert!(components.cameras[camera_key].camera.aspect_ratio().is_none());
+
+    pool.shutdown();
+}
+
+#[test]
+fn prepare_scene_handles_dirty_cameras_and_viewports() {
+    let mut scene = RSGScene::<RSGComponentLinks, RSGSceneObserver>::new();
+    let mut components = RSGComponentContainer::default();
+    scene.set_observer(RSGSceneObserver::new());
+    let root_key = components.add_default_root(&mut scene);
+
+    // ROOT(CAM1, CAM2, VP(MESH))
+    let cam1_key = scene.append(root_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components)
+        .transform(glm::translation(&glm::vec3(0.0, 0.0, 10.0)))
+        .camera(make_camera())
+        .links()));
+    let cam2_key = scene.append(root_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components)
+        .transform(glm::translation(&glm::vec3(0.0, 0.0, 100.0)))
+        .links()));
+    let vp_key = scene.append(root_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components).viewport(None, Some(cam1_key)).links()));
+    let mesh_key = scene.append(vp_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components).transform(glm::one()).opacity(1.0).mesh(make_mesh()).links()));
+
+    let pool = scoped_pool::Pool::new(2);
+    let mut render_lists = vec![];
+    let mut work_list = vec![];
+    let mesh_component_key = scene.get_component_links(mesh_key).mesh_key.unwrap();
+
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    assert!(render_lists[0].opaque_list.len() == 1 && render_lists[0].opaque_list[0].1 == 10.0);
+    assert!(components.meshes[mesh_component_key].viewport_node_key == Some(vp_key));
+
+    // CAM2 gets a camera component, and VP switches to it
+    let camera_key = components.cameras.insert(RSGCameraComponent::new(make_camera()));
+    scene.get_component_links_mut(cam2_key).camera_key = Some(camera_key);
+    scene.mark_dirty(cam2_key, RSGDirtyFlags::CAMERA);
+    components.viewports[scene.get_component_links(vp_key).viewport_key.unwrap()].camera_node_key = Some(cam2_key);
+    scene.mark_dirty(vp_key, RSGDirtyFlags::VIEWPORT);
+    assert!(scene.observer().unwrap().dirty_world_roots.is_empty());
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    assert!(components.cameras[camera_key].world_properties.position == glm::vec3(0.0, 0.0, 100.0));
+    assert!(render_lists[0].opaque_list.len() == 1 && render_lists[0].opaque_list[0].1 == 100.0);
+
+    // a viewport with a removed camera renders nothing
+    scene.remove(cam2_key);
+    scene.mark_dirty(vp_key, RSGDirtyFlags::VIEWPORT);
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    assert!(render_lists[0].camera_node_key.is_none() && render_lists[0].opaque_list.is_empty());
+    assert!(components.meshes[mesh_component_key].viewport_node_key.is_none());
+
+    // nodes marked dirty and removed before the next prepare_scene are skipped
+    scene.mark_dirty(cam1_key, RSGDirtyFlags::CAMERA | RSGDirtyFlags::TRANSFORM);
+    scene.mark_dirty(mesh_key, RSGDirtyFlags::MESH | RSGDirtyFlags::MATERIAL | RSGDirtyFlags::MATERIAL_VALUES);
+    scene.mark_dirty(vp_key, RSGDirtyFlags::VIEWPORT | RSGDirtyFlags::OPACITY);
+    scene.remove(vp_key);
+    scene.remove(cam1_key);
+    prepare(&mut scene, &mut components, &mut render_lists, &mut work_list, &pool);
+    assert!(render_lists.is_empty());
+
+    pool.shutdown();
+}
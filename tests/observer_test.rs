@@ -0,0 +1,148 @@
+#![allow(clippy::assertions_on_constants)]
+
+use rsg::scene::*;
+use rsg::observer::*;
+use std::sync::{Arc, Mutex};
+
+#[derive(Default)]
+struct TestObserver {
+    events: Vec<RSGEvent>
+}
+
+impl RSGObserver for TestObserver {
+    fn notify(&mut self, event: RSGEvent) {
+        self.events.push(event);
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct TestCompLinks;
+
+struct OrderObserver {
+    id: u32,
+    log: Arc<Mutex<Vec<u32>>>
+}
+
+impl RSGObserver for OrderObserver {
+    fn notify(&mut self, _event: RSGEvent) {
+        self.log.lock().unwrap().push(self.id);
+    }
+}
+
+#[test]
+fn tuple_of_observers() {
+    let mut scene = RSGScene::<TestCompLinks, (TestObserver, RSGSceneObserver)>::new();
+    scene.set_observer((TestObserver::default(), RSGSceneObserver::new()));
+    let root_key = scene.set_root(RSGNode::new());
+    let node_key = scene.append(root_key, RSGNode::new());
+    scene.mark_dirty(node_key, RSGDirtyFlags::OPACITY);
+
+    let (test_obs, scene_obs) = scene.take_observer().unwrap();
+    assert!(test_obs.events == [RSGEvent::SubtreeAddedOrReattached(root_key),
+        RSGEvent::SubtreeAddedOrReattached(node_key),
+        RSGEvent::Dirty(node_key, RSGDirtyFlags::OPACITY)]);
+    assert!(scene_obs.hierarchy_changed);
+    assert!(scene_obs.dirty_opacity_roots.as_slice() == [root_key, node_key, node_key]);
+}
+
+#[test]
+fn subscribe_and_unsubscribe() {
+    let mut scene = RSGScene::<TestCompLinks, RSGObserverList>::new();
+    scene.set_observer(RSGObserverList::new());
+    let log = Arc::new(Mutex::new(vec![]));
+    let shared = Arc::new(Mutex::new(TestObserver::default()));
+
+    let list = scene.observer_mut().unwrap();
+    let h1 = list.subscribe(Box::new(OrderObserver { id: 1, log: log.clone() }));
+    let h2 = list.subscribe(Box::new(shared.clone()));
+    let h3 = list.subscribe(Box::new(OrderObserver { id: 3, log: log.clone() }));
+    assert!(list.len() == 3);
+
+    let root_key = scene.set_root(RSGNode::new());
+    assert!(*log.lock().unwrap() == [1, 3]);
+    assert!(shared.lock().unwrap().events == [RSGEvent::SubtreeAddedOrReattached(root_key)]);
+
+    // unsubscribing keeps the order of the others
+    let list = scene.observer_mut().unwrap();
+    assert!(list.unsubscribe(h1).is_some());
+    assert!(list.unsubscribe(h1).is_none());
+    assert!(!list.is_subscribed(h1) && list.is_subscribed(h2) && list.is_subscribed(h3));
+    let h4 = list.subscribe(Box::new(OrderObserver { id: 4, log: log.clone() }));
+    let node_key = scene.append(root_key, RSGNode::new());
+    assert!(*log.lock().unwrap() == [1, 3, 3, 4]);
+    assert!(shared.lock().unwrap().events == [RSGEvent::SubtreeAddedOrReattached(root_key),
+        RSGEvent::SubtreeAddedOrReattached(node_key)]);
+
+    let list = scene.observer_mut().unwrap();
+    list.unsubscribe(h2);
+    list.unsubscribe(h3);
+    list.unsubscribe(h4);
+    assert!(list.is_empty());
+    scene.remove(node_key);
+    assert!(shared.lock().unwrap().events.len() == 2);
+    assert!(log.lock().unwrap().len() == 4);
+}
+
+#[test]
+fn dirty_with_multiple_flags() {
+    let mut scene = RSGScene::<TestCompLinks, RSGSceneObserver>::new();
+    let root_key = scene.set_root(RSGNode::new());
+    let node_key = scene.append(root_key, RSGNode::new());
+    scene.set_observer(RSGSceneObserver::new());
+
+    scene.mark_dirty(node_key, RSGDirtyFlags::TRANSFORM | RSGDirtyFlags::OPACITY | RSGDirtyFlags::MESH);
+    scene.mark_dirty(root_key, RSGDirtyFlags::CAMERA | RSGDirtyFlags::VIEWPORT);
+    scene.mark_dirty(node_key, RSGDirtyFlags::all());
+
+    let obs = scene.take_observer().unwrap();
+    assert!(obs.changed && !obs.hierarchy_changed);
+    assert!(obs.dirty_world_roots.as_slice() == [node_key, node_key]);
+    assert!(obs.dirty_opacity_roots.as_slice() == [node_key, node_key]);
+    assert!(obs.dirty_material_nodes.as_slice() == [node_key]);
+    assert!(obs.dirty_material_value_nodes.as_slice() == [node_key]);
+    assert!(obs.dirty_mesh_nodes.as_slice() == [node_key, node_key]);
+    assert!(obs.dirty_camera_nodes.as_slice() == [root_key, node_key]);
+    assert!(obs.dirty_viewport_nodes.as_slice() == [root_key, node_key]);
+}
+
+#[test]
+fn normalize_dirty_lists() {
+    let mut scene = RSGScene::<TestCompLinks, RSGSceneObserver>::new();
+    let root_key = scene.set_root(RSGNode::new());
+    let a = scene.append(root_key, RSGNode::new());
+    let a1 = scene.append(a, RSGNode::new());
+    let a11 = scene.append(a1, RSGNode::new());
+    let b = scene.append(root_key, RSGNode::new());
+    let b1 = scene.append(b, RSGNode::new());
+    let c = scene.append(root_key, RSGNode::new());
+    scene.set_observer(RSGSceneObserver::new());
+
+    scene.mark_dirty(a11, RSGDirtyFlags::TRANSFORM | RSGDirtyFlags::MESH);
+    scene.mark_dirty(b1, RSGDirtyFlags::TRANSFORM | RSGDirtyFlags::OPACITY);
+    scene.mark_dirty(a, RSGDirtyFlags::TRANSFORM);
+    scene.mark_dirty(b1, RSGDirtyFlags::TRANSFORM | RSGDirtyFlags::MESH);
+    scene.mark_dirty(c, RSGDirtyFlags::TRANSFORM | RSGDirtyFlags::OPACITY | RSGDirtyFlags::MESH);
+    scene.mark_dirty(a11, RSGDirtyFlags::OPACITY);
+    scene.mark_dirty(a1, RSGDirtyFlags::OPACITY);
+    scene.remove(c);
+    let d = scene.append(b1, RSGNode::new());
+    let detached = scene.detach(b);
+
+    let mut obs = scene.take_observer().unwrap();
+    obs.normalize(&scene);
+    assert!(obs.dirty_world_roots.as_slice() == [a]);
+    assert!(obs.dirty_opacity_roots.as_slice() == [a1]);
+    assert!(obs.dirty_mesh_nodes.as_slice() == [a11]);
+    assert!(obs.added_roots.is_empty());
+    assert!(obs.removed_roots.as_slice() == [c, b]);
+
+    scene.set_observer(obs);
+    let b = scene.reattach(detached, root_key, RSGNodePosition::Append);
+    scene.mark_dirty(d, RSGDirtyFlags::TRANSFORM);
+    let mut obs = scene.take_observer().unwrap();
+    obs.normalize(&scene);
+    assert!(obs.dirty_world_roots.as_slice() == [a, b]);
+    assert!(obs.dirty_opacity_roots.as_slice() == [a1, b]);
+    assert!(obs.dirty_mesh_nodes.as_slice() == [a11, b]);
+    assert!(obs.added_roots.as_slice() == [b]);
+}
@@ -1,4 +1,7 @@
-use rsg::scene::{RSGNode, RSGScene, RSGEvent, RSGObserver, RSGSubtreeAddTransaction};
+#![allow(clippy::assertions_on_constants, clippy::explicit_counter_loop)]
+
+use rsg::scene::{RSGNode, RSGNodeKey, RSGScene, RSGEvent, RSGObserver, RSGSubtreeAddTransaction, RSGSceneTransaction, RSGNodePosition, RSGVisitResult, RSGSceneError, RSGSceneViolation, RSGNodeLink, RSGDetachedSubtree, RSGHierarchyEvent, RSGHierarchyObserver, RSGNodePlacement, RSGDirtyFlags};
+use std::sync::{Arc, Mutex};
 
 #[derive(Clone, Copy, Default, PartialEq)]
 struct TestCompLinks {
@@ -27,13 +30,21 @@ impl TestObserver {
 
 type TestScene = RSGScene::<TestCompLinks, TestObserver>;
 
+fn assert_valid(scene: &TestScene) {
+    if let Err(violations) = scene.validate() {
+        panic!("scene integrity violations: {:?}", violations);
+    }
+}
+
 #[test]
 fn set_root() {
     let mut scene = TestScene::new();
     let root_key = scene.set_root(RSGNode::new());
+    assert_valid(&scene);
     assert!(scene.is_valid(root_key));
     assert!(scene.node_count() == 1);
     let component_links = scene.clear();
+    assert_valid(&scene);
     assert!(component_links.is_empty());
     assert!(scene.is_valid(root_key));
     assert!(scene.node_count() == 1);
@@ -46,9 +57,11 @@ fn append_and_observe() {
 
     // ROOT
     let root_key = scene.set_root(RSGNode::new());
+    assert_valid(&scene);
 
     // ROOT(NODE1)
     let node1_key = scene.append(root_key, RSGNode::new());
+    assert_valid(&scene);
     assert!(scene.is_valid(node1_key));
     assert!(scene.node_count() == 2);
     let mut obs = scene.take_observer().unwrap();
@@ -67,6 +80,7 @@ fn append_and_observe() {
     scene.set_observer(obs);
     // ROOT(NODE1, NODE2)
     let node2_key = scene.append(root_key, RSGNode::new());
+    assert_valid(&scene);
     assert!(scene.is_valid(node2_key));
     assert!(scene.node_count() == 3);
     obs = scene.take_observer().unwrap();
@@ -83,6 +97,7 @@ fn append_and_observe() {
     assert!(scene[node2_key].links() == (Some(node2_key), Some(root_key), None, None, Some(node1_key), None));
 
     let component_links = scene.clear();
+    assert_valid(&scene);
     assert!(component_links.len() == 2);
     assert!(scene.node_count() == 1);
     assert!(scene.is_valid(root_key));
@@ -95,24 +110,31 @@ fn append_subtree_and_observe() {
     let mut scene = TestScene::new();
     // ROOT
     let root_key = scene.set_root(RSGNode::new());
+    assert_valid(&scene);
 
     scene.set_observer(TestObserver::new());
     // ROOT(NODE1)
     let node1_key = scene.append(root_key, RSGNode::new());
+    assert_valid(&scene);
     assert!(scene.is_valid(node1_key));
 
     let mut node2_t = RSGSubtreeAddTransaction::new();
     let node2_key = scene.append_with_transaction(root_key, RSGNode::new(), &mut node2_t);
+    assert_valid(&scene);
     assert!(!scene.is_valid(node2_key));
     let node21_key = scene.append_with_transaction(node2_key, RSGNode::new(), &mut node2_t);
+    assert_valid(&scene);
     assert!(!scene.is_valid(node21_key));
     let node211_key = scene.append_with_transaction(node21_key, RSGNode::new(), &mut node2_t);
+    assert_valid(&scene);
     assert!(!scene.is_valid(node211_key));
     let node22_key = scene.append_with_transaction(node2_key, RSGNode::new(), &mut node2_t);
+    assert_valid(&scene);
     assert!(!scene.is_valid(node22_key));
 
     // ROOT(NODE1, NODE2(NODE21(NODE211), NODE22))
     scene.commit(node2_t);
+    assert_valid(&scene);
 
     assert!(scene.is_valid(node2_key));
     assert!(scene.is_valid(node21_key));
@@ -147,24 +169,31 @@ fn start_append_subtree_then_rollback() {
     let mut scene = TestScene::new();
     // ROOT
     let root_key = scene.set_root(RSGNode::new());
+    assert_valid(&scene);
 
     scene.set_observer(TestObserver::new());
     // ROOT(NODE1)
     let node1_key = scene.append(root_key, RSGNode::new());
+    assert_valid(&scene);
     assert!(scene.is_valid(node1_key));
     assert!(scene.node_count() == 2);
 
     let mut node2_t = RSGSubtreeAddTransaction::new();
     let node2_key = scene.append_with_transaction(root_key, RSGNode::new(), &mut node2_t);
+    assert_valid(&scene);
     let node21_key = scene.append_with_transaction(node2_key, RSGNode::new(), &mut node2_t);
+    assert_valid(&scene);
     let node211_key = scene.append_with_transaction(node21_key, RSGNode::new(), &mut node2_t);
+    assert_valid(&scene);
     let node22_key = scene.append_with_transaction(node2_key, RSGNode::new(), &mut node2_t);
+    assert_valid(&scene);
     // in the arena but not linked
     assert!(scene.node_count() == 6);
     assert!(scene[root_key].links() == (Some(root_key), None, Some(node1_key), Some(node1_key), None, None));
 
     // would have been ROOT(NODE1, NODE2(NODE21(NODE211), NODE22)) if this was a commit()
     scene.rollback(node2_t);
+    assert_valid(&scene);
     // now gone from the arena too
     assert!(scene.node_count() == 2);
     assert!(!scene.is_valid(node2_key));
@@ -186,10 +215,12 @@ fn prepend_and_observe() {
     let mut scene = TestScene::new();
     // ROOT
     let root_key = scene.set_root(RSGNode::new());
+    assert_valid(&scene);
 
     scene.set_observer(TestObserver::new());
     // ROOT(NODE1)
     let node1_key = scene.prepend(root_key, RSGNode::new());
+    assert_valid(&scene);
     assert!(scene.is_valid(node1_key));
     assert!(scene.node_count() == 2);
     let mut obs = scene.take_observer().unwrap();
@@ -203,6 +234,7 @@ fn prepend_and_observe() {
     scene.set_observer(obs);
     // ROOT(NODE2, NODE1)
     let node2_key = scene.prepend(root_key, RSGNode::new());
+    assert_valid(&scene);
     assert!(scene.is_valid(node2_key));
     assert!(scene.node_count() == 3);
     obs = scene.take_observer().unwrap();
@@ -224,24 +256,31 @@ fn prepend_subtree_and_observe() {
     let mut scene = TestScene::new();
     // ROOT
     let root_key = scene.set_root(RSGNode::new());
+    assert_valid(&scene);
 
     scene.set_observer(TestObserver::new());
     // ROOT(NODE1)
     let node1_key = scene.append(root_key, RSGNode::new());
+    assert_valid(&scene);
     assert!(scene.is_valid(node1_key));
 
     let mut node2_t = RSGSubtreeAddTransaction::new();
     let node2_key = scene.prepend_with_transaction(root_key, RSGNode::new(), &mut node2_t);
+    assert_valid(&scene);
     assert!(!scene.is_valid(node2_key));
     let node21_key = scene.prepend_with_transaction(node2_key, RSGNode::new(), &mut node2_t);
+    assert_valid(&scene);
     assert!(!scene.is_valid(node21_key));
     let node211_key = scene.prepend_with_transaction(node21_key, RSGNode::new(), &mut node2_t);
+    assert_valid(&scene);
     assert!(!scene.is_valid(node211_key));
     let node22_key = scene.prepend_with_transaction(node2_key, RSGNode::new(), &mut node2_t);
+    assert_valid(&scene);
     assert!(!scene.is_valid(node22_key));
 
     // ROOT(NODE2(NODE22, NODE21(NODE211)), NODE1)
     scene.commit(node2_t);
+    assert_valid(&scene);
 
     assert!(scene.is_valid(node2_key));
     assert!(scene.is_valid(node21_key));
@@ -278,19 +317,26 @@ fn insert_before_after_and_observe() {
 
     // ROOT
     let root_key = scene.set_root(RSGNode::new());
+    assert_valid(&scene);
 
     // ROOT(NODE1)
     let node1_key = scene.append(root_key, RSGNode::new());
+    assert_valid(&scene);
     // ROOT(NODE1, NODE2)
     let node2_key = scene.insert_after(node1_key, RSGNode::new());
+    assert_valid(&scene);
     // ROOT(NODE1, NODE2(NODE21))
     let node21_key = scene.append(node2_key, RSGNode::new());
+    assert_valid(&scene);
     // ROOT(NODE1, NODE2(NODE22, NODE21))
     let node22_key = scene.insert_before(node21_key, RSGNode::new());
+    assert_valid(&scene);
     // ROOT(NODE1, NODE3, NODE2(NODE22, NODE21))
     let node3_key = scene.insert_after(node1_key, RSGNode::new());
+    assert_valid(&scene);
     // ROOT(NODE4, NODE1, NODE3, NODE2(NODE22, NODE21))
     let node4_key = scene.insert_before(node1_key, RSGNode::new());
+    assert_valid(&scene);
 
     assert!(scene.is_valid(node1_key));
     assert!(scene.is_valid(node2_key));
@@ -348,20 +394,27 @@ fn remove_and_observe() {
     let mut scene = TestScene::new();
     // ROOT
     let root_key = scene.set_root(RSGNode::new());
+    assert_valid(&scene);
 
     scene.set_observer(TestObserver::new());
     // ROOT(NODE1)
     let node1_key = scene.append(root_key, RSGNode::new());
+    assert_valid(&scene);
     assert!(scene.is_valid(node1_key));
 
     let mut node2_t = RSGSubtreeAddTransaction::new();
     let node2_key = scene.append_with_transaction(root_key, RSGNode::new(), &mut node2_t);
+    assert_valid(&scene);
     assert!(!scene.is_valid(node2_key));
     let node21_key = scene.append_with_transaction(node2_key, RSGNode::new(), &mut node2_t);
+    assert_valid(&scene);
     let node211_key = scene.append_with_transaction(node21_key, RSGNode::new(), &mut node2_t);
+    assert_valid(&scene);
     let node22_key = scene.append_with_transaction(node2_key, RSGNode::new(), &mut node2_t);
+    assert_valid(&scene);
     // ROOT(NODE1, NODE2(NODE21(NODE211), NODE22))
     scene.commit(node2_t);
+    assert_valid(&scene);
     assert!(scene.node_count() == 6);
 
     let mut obs = scene.take_observer().unwrap();
@@ -371,6 +424,7 @@ fn remove_and_observe() {
 
     // ROOT(NODE1)
     scene.remove(node2_key);
+    assert_valid(&scene);
     assert!(scene.node_count() == 2);
     assert!(!scene.is_valid(node2_key));
     assert!(!scene.is_valid(node21_key));
@@ -389,6 +443,7 @@ fn remove_and_observe() {
     scene.set_observer(obs);
     // ROOT
     let component_links = scene.remove_children(root_key);
+    assert_valid(&scene);
     assert!(component_links.len() == 1);
     assert!(scene.node_count() == 1);
 
@@ -407,17 +462,23 @@ fn remove_single_child_without_children_and_observe()
     let mut scene = TestScene::new();
     // ROOT
     let root_key = scene.set_root(RSGNode::new());
+    assert_valid(&scene);
 
     // ROOT(NODE1)
     let node1_key = scene.append(root_key, RSGNode::new());
+    assert_valid(&scene);
     // ROOT(NODE1, NODE2)
     let node2_key = scene.append(root_key, RSGNode::new());
+    assert_valid(&scene);
     // ROOT(NODE1(NODE11), NODE2)
     let node11_key = scene.append(node1_key, RSGNode::new());
+    assert_valid(&scene);
     // ROOT(NODE1(NODE11(NODE111), NODE2)
     let node111_key = scene.append(node11_key, RSGNode::new());
+    assert_valid(&scene);
     // ROOT(NODE1(NODE11(NODE111, NODE112)), NODE2)
     let node112_key = scene.append(node11_key, RSGNode::new());
+    assert_valid(&scene);
 
     assert!(scene.node_count() == 6);
     {
@@ -432,6 +493,7 @@ fn remove_single_child_without_children_and_observe()
 
     // ROOT(NODE1(NODE111, NODE112), NODE2)
     scene.remove_without_children(node11_key);
+    assert_valid(&scene);
 
     assert!(scene.node_count() == 5);
     assert!(!scene.is_valid(node11_key));
@@ -474,9 +536,11 @@ fn remove_single_child_without_children_and_observe()
 
     // ROOT(NODE1(NODE111, NODE112), NODE2(NODE21))
     let node21_key = scene.append(node2_key, RSGNode::new());
+    assert_valid(&scene);
     assert!(scene.node_count() == 6);
     // ROOT(NODE1(NODE111, NODE112), NODE21)
     scene.remove_without_children(node2_key);
+    assert_valid(&scene);
     assert!(scene.node_count() == 5);
     assert!(!scene.is_valid(node2_key));
     assert!(scene.is_valid(node21_key));
@@ -493,24 +557,32 @@ fn remove_without_children_and_observe()
     let mut scene = TestScene::new();
     // ROOT
     let root_key = scene.set_root(RSGNode::new());
+    assert_valid(&scene);
 
     // ROOT(NODE1)
     let node1_key = scene.append(root_key, RSGNode::new());
+    assert_valid(&scene);
     // ROOT(NODE1, NODE2)
     let node2_key = scene.append(root_key, RSGNode::new());
+    assert_valid(&scene);
     // ROOT(NODE1, NODE2(NODE21))
     let node21_key = scene.append(node2_key, RSGNode::new());
+    assert_valid(&scene);
     // ROOT(NODE1, NODE2(NODE21, NODE22))
     let node22_key = scene.append(node2_key, RSGNode::new());
+    assert_valid(&scene);
     // ROOT(NODE1, NODE2(NODE21, NODE22(NODE221)))
     let node221_key = scene.append(node22_key, RSGNode::new());
+    assert_valid(&scene);
     // ROOT(NODE1, NODE2(NODE21, NODE22(NODE221)), NODE3)
     let node3_key = scene.append(root_key, RSGNode::new());
+    assert_valid(&scene);
     assert!(scene.node_count() == 7);
     scene.set_observer(TestObserver::new());
 
     // ROOT(NODE1, NODE21, NODE22(NODE221), NODE3)
     scene.remove_without_children(node2_key);
+    assert_valid(&scene);
 
     assert!(scene.node_count() == 6);
     assert!(scene.is_valid(node1_key));
@@ -565,16 +637,20 @@ fn insert_under_and_observe()
     let mut scene = TestScene::new();
     // ROOT
     let root_key = scene.set_root(RSGNode::new());
+    assert_valid(&scene);
 
     // ROOT(NODE1)
     let node1_key = scene.append(root_key, RSGNode::new());
+    assert_valid(&scene);
     // ROOT(NODE1, NODE2)
     let node2_key = scene.append(root_key, RSGNode::new());
+    assert_valid(&scene);
 
     scene.set_observer(TestObserver::new());
 
     // ROOT(NODE3(NODE1, NODE2))
     let node3_key = scene.insert_under(root_key, RSGNode::new());
+    assert_valid(&scene);
 
     {
         // key, parent, first_child, last_child, prev_sibling, next_sibling
@@ -606,8 +682,10 @@ fn insert_under_and_observe()
 
     // ROOT(NODE3(NODE1, NODE2), NODE4)
     let node4_key = scene.append(root_key, RSGNode::new());
+    assert_valid(&scene);
     // ROOT(NODE3(NODE5(NODE1, NODE2)), NODE4)
     let node5_key = scene.insert_under(node3_key, RSGNode::new());
+    assert_valid(&scene);
 
     {
         assert!(scene[node1_key].links() == (Some(node1_key), Some(node5_key), None, None, None, Some(node2_key)));
@@ -641,10 +719,327 @@ fn insert_under_and_observe()
     }
 }
 
+#[test]
+fn move_subtree_and_observe()
+{
+    let mut scene = TestScene::new();
+    // ROOT
+    let root_key = scene.set_root(RSGNode::new());
+    assert_valid(&scene);
+
+    // ROOT(NODE1(NODE11, NODE12), NODE2(NODE21))
+    let node1_key = scene.append(root_key, RSGNode::new());
+    assert_valid(&scene);
+    let node2_key = scene.append(root_key, RSGNode::new());
+    assert_valid(&scene);
+    let node11_key = scene.append(node1_key, RSGNode::new());
+    assert_valid(&scene);
+    let node12_key = scene.append(node1_key, RSGNode::new());
+    assert_valid(&scene);
+    let node21_key = scene.append(node2_key, RSGNode::new());
+    assert_valid(&scene);
+
+    scene.set_observer(TestObserver::new());
+
+    // ROOT(NODE2(NODE21, NODE1(NODE11, NODE12)))
+    scene.move_subtree(node1_key, node2_key, RSGNodePosition::Append);
+    assert_valid(&scene);
+    assert!(scene.node_count() == 6);
+    assert!(scene.is_valid(node1_key));
+    assert!(scene.is_valid(node11_key));
+    assert!(scene.is_valid(node12_key));
+
+    {
+        // key, parent, first_child, last_child, prev_sibling, next_sibling
+        assert!(scene[root_key].links() == (Some(root_key), None, Some(node2_key), Some(node2_key), None, None));
+        assert!(scene[node2_key].links() == (Some(node2_key), Some(root_key), Some(node21_key), Some(node1_key), None, None));
+        assert!(scene[node21_key].links() == (Some(node21_key), Some(node2_key), None, None, None, Some(node1_key)));
+        assert!(scene[node1_key].links() == (Some(node1_key), Some(node2_key), Some(node11_key), Some(node12_key), Some(node21_key), None));
+        assert!(scene[node11_key].links() == (Some(node11_key), Some(node1_key), None, None, None, Some(node12_key)));
+        assert!(scene[node12_key].links() == (Some(node12_key), Some(node1_key), None, None, Some(node11_key), None));
+    }
+
+    let mut obs = scene.take_observer().unwrap();
+    assert!(obs.events.len() == 2);
+    if let RSGEvent::SubtreeAboutToBeTemporarilyDetached(key) = obs.events[0] {
+        assert!(key == node1_key);
+    } else {
+        assert!(false);
+    }
+    if let RSGEvent::SubtreeAddedOrReattached(key) = obs.events[1] {
+        assert!(key == node1_key);
+    } else {
+        assert!(false);
+    }
+
+    scene.set_observer(obs);
+
+    // ROOT(NODE2(NODE1(NODE11), NODE12, NODE21))
+    scene.move_subtree(node12_key, node2_key, RSGNodePosition::Before(node21_key));
+    assert_valid(&scene);
+    // ROOT(NODE2(NODE12, NODE1(NODE11), NODE21))
+    scene.move_subtree(node1_key, node2_key, RSGNodePosition::After(node12_key));
+    assert_valid(&scene);
+    // ROOT(NODE11, NODE2(NODE12, NODE1, NODE21))
+    scene.move_subtree(node11_key, root_key, RSGNodePosition::Prepend);
+    assert_valid(&scene);
+    assert!(scene.node_count() == 6);
+
+    {
+        assert!(scene[root_key].links() == (Some(root_key), None, Some(node11_key), Some(node2_key), None, None));
+        assert!(scene[node11_key].links() == (Some(node11_key), Some(root_key), None, None, None, Some(node2_key)));
+        assert!(scene[node2_key].links() == (Some(node2_key), Some(root_key), Some(node12_key), Some(node21_key), Some(node11_key), None));
+        assert!(scene[node12_key].links() == (Some(node12_key), Some(node2_key), None, None, None, Some(node1_key)));
+        assert!(scene[node1_key].links() == (Some(node1_key), Some(node2_key), None, None, Some(node12_key), Some(node21_key)));
+        assert!(scene[node21_key].links() == (Some(node21_key), Some(node2_key), None, None, Some(node1_key), None));
+    }
+
+    obs = scene.take_observer().unwrap();
+    assert!(obs.events.len() == 8);
+    let expected = [node12_key, node1_key, node11_key];
+    for (i, key) in expected.iter().enumerate() {
+        assert!(obs.events[2 + i * 2] == RSGEvent::SubtreeAboutToBeTemporarilyDetached(*key));
+        assert!(obs.events[2 + i * 2 + 1] == RSGEvent::SubtreeAddedOrReattached(*key));
+    }
+}
+
+#[test]
+#[should_panic]
+fn move_subtree_into_own_descendant() {
+    let mut scene = TestScene::new();
+    // ROOT(NODE1(NODE11))
+    let root_key = scene.set_root(RSGNode::new());
+    assert_valid(&scene);
+    let node1_key = scene.append(root_key, RSGNode::new());
+    assert_valid(&scene);
+    let node11_key = scene.append(node1_key, RSGNode::new());
+    assert_valid(&scene);
+    scene.move_subtree(node1_key, node11_key, RSGNodePosition::Append);
+    assert_valid(&scene);
+}
+
+#[test]
+#[should_panic]
+fn move_subtree_before_node_of_other_parent() {
+    let mut scene = TestScene::new();
+    // ROOT(NODE1(NODE11), NODE2)
+    let root_key = scene.set_root(RSGNode::new());
+    let node1_key = scene.append(root_key, RSGNode::new());
+    let node11_key = scene.append(node1_key, RSGNode::new());
+    let node2_key = scene.append(root_key, RSGNode::new());
+    assert_valid(&scene);
+    scene.move_subtree(node2_key, root_key, RSGNodePosition::Before(node11_key));
+}
+
+#[test]
+fn reorder_siblings_and_observe() {
+    let mut scene = TestScene::new();
+    // ROOT(NODE1, NODE2, NODE3(NODE31), NODE4)
+    let root_key = scene.set_root(RSGNode::new());
+    assert_valid(&scene);
+    let make_node = |handle| {
+        RSGNode::with_component_links(TestCompLinks {
+            transform_handle: Some(handle),
+            geometry_handle: None,
+            material_handle: None
+        })
+    };
+    let node1_key = scene.append(root_key, make_node(4));
+    assert_valid(&scene);
+    let node2_key = scene.append(root_key, make_node(3));
+    assert_valid(&scene);
+    let node3_key = scene.append(root_key, make_node(1));
+    assert_valid(&scene);
+    let node4_key = scene.append(root_key, make_node(2));
+    assert_valid(&scene);
+    let node31_key = scene.append(node3_key, RSGNode::new());
+    assert_valid(&scene);
+
+    let children = |scene: &TestScene| -> Vec<RSGNodeKey> {
+        scene.children(root_key).collect()
+    };
+
+    scene.set_observer(TestObserver::new());
+
+    // ROOT(NODE2, NODE3(NODE31), NODE1, NODE4)
+    scene.move_to_index(node1_key, 2);
+    assert_valid(&scene);
+    assert!(children(&scene) == [node2_key, node3_key, node1_key, node4_key]);
+    // ROOT(NODE4, NODE3(NODE31), NODE1, NODE2)
+    scene.swap_siblings(node2_key, node4_key);
+    assert_valid(&scene);
+    assert!(children(&scene) == [node4_key, node3_key, node1_key, node2_key]);
+    // ROOT(NODE3(NODE31), NODE1, NODE2, NODE4)
+    scene.raise_to_front(node4_key);
+    assert_valid(&scene);
+    assert!(children(&scene) == [node3_key, node1_key, node2_key, node4_key]);
+    // ROOT(NODE2, NODE3(NODE31), NODE1, NODE4)
+    scene.lower_to_back(node2_key);
+    assert_valid(&scene);
+    assert!(children(&scene) == [node2_key, node3_key, node1_key, node4_key]);
+    // no change
+    scene.lower_to_back(node2_key);
+    assert_valid(&scene);
+    // ROOT(NODE3(NODE31), NODE4, NODE2, NODE1)
+    scene.sort_children_by(root_key, |node| node.get_component_links().transform_handle);
+    assert_valid(&scene);
+    assert!(children(&scene) == [node3_key, node4_key, node2_key, node1_key]);
+
+    // key, parent, first_child, last_child, prev_sibling, next_sibling
+    assert!(scene[root_key].links() == (Some(root_key), None, Some(node3_key), Some(node1_key), None, None));
+    assert!(scene[node3_key].links() == (Some(node3_key), Some(root_key), Some(node31_key), Some(node31_key), None, Some(node4_key)));
+    assert!(scene[node4_key].links() == (Some(node4_key), Some(root_key), None, None, Some(node3_key), Some(node2_key)));
+    assert!(scene[node2_key].links() == (Some(node2_key), Some(root_key), None, None, Some(node4_key), Some(node1_key)));
+    assert!(scene[node1_key].links() == (Some(node1_key), Some(root_key), None, None, Some(node2_key), None));
+    assert!(scene[node31_key].links() == (Some(node31_key), Some(node3_key), None, None, None, None));
+
+    let obs = scene.take_observer().unwrap();
+    assert!(obs.events.len() == 5);
+    for event in obs.events {
+        assert!(event == RSGEvent::ChildrenReordered(root_key));
+    }
+}
+
+#[test]
+fn append_two_subtrees_in_one_transaction_and_observe() {
+    let mut scene = TestScene::new();
+    // ROOT(NODE1)
+    let root_key = scene.set_root(RSGNode::new());
+    assert_valid(&scene);
+    let node1_key = scene.append(root_key, RSGNode::new());
+    assert_valid(&scene);
+
+    scene.set_observer(TestObserver::new());
+
+    // ROOT(NODE1(NODE11(NODE111)), NODE2)
+    let mut t = RSGSubtreeAddTransaction::new();
+    let node11_key = scene.append_with_transaction(node1_key, RSGNode::new(), &mut t);
+    assert_valid(&scene);
+    let node111_key = scene.append_with_transaction(node11_key, RSGNode::new(), &mut t);
+    assert_valid(&scene);
+    let node2_key = scene.append_with_transaction(root_key, RSGNode::new(), &mut t);
+    assert_valid(&scene);
+    scene.commit(t);
+    assert_valid(&scene);
+    assert!(scene.is_valid(node11_key) && scene.is_valid(node111_key) && scene.is_valid(node2_key));
+
+    let obs = scene.take_observer().unwrap();
+    assert!(obs.events == [RSGEvent::SubtreeAddedOrReattached(node11_key), RSGEvent::SubtreeAddedOrReattached(node2_key)]);
+}
+
+fn build_transaction_test_scene(scene: &mut TestScene) -> [RSGNodeKey; 5] {
+    // ROOT(NODE1(NODE11), NODE2, NODE3)
+    let root_key = scene.set_root(RSGNode::new());
+    assert_valid(scene);
+    let node1_key = scene.append(root_key, RSGNode::new());
+    assert_valid(scene);
+    let node2_key = scene.append(root_key, RSGNode::new());
+    assert_valid(scene);
+    let node3_key = scene.append(root_key, RSGNode::new());
+    assert_valid(scene);
+    let node11_key = scene.append(node1_key, RSGNode::new());
+    assert_valid(scene);
+    [root_key, node1_key, node2_key, node3_key, node11_key]
+}
+
+fn fill_transaction(scene: &mut TestScene, keys: &[RSGNodeKey; 5], t: &mut RSGSceneTransaction) -> [RSGNodeKey; 4] {
+    let [root_key, node1_key, node2_key, node3_key, node11_key] = *keys;
+    // ROOT(NODE1, NODE2(NODE11), NODE3)
+    scene.move_subtree_in_transaction(node11_key, node2_key, RSGNodePosition::Append, t);
+    assert_valid(scene);
+    // ROOT(NODE2(NODE11), NODE3)
+    scene.remove_in_transaction(node1_key, t);
+    assert_valid(scene);
+    // ROOT(NODE2(NODE11), NODE3, NODE4(NODE41))
+    let node4_key = scene.append_in_transaction(root_key, RSGNode::new(), t);
+    assert_valid(scene);
+    let node41_key = scene.append_in_transaction(node4_key, RSGNode::new(), t);
+    assert_valid(scene);
+    // ROOT(NODE5, NODE2(NODE11), NODE3, NODE4(NODE41))
+    let node5_key = scene.insert_before_in_transaction(node2_key, RSGNode::new(), t);
+    assert_valid(scene);
+    // ROOT(NODE5, NODE2(NODE11), NODE4(NODE41))
+    scene.remove_in_transaction(node3_key, t);
+    assert_valid(scene);
+    // ROOT(NODE5, NODE2(NODE6(NODE11)), NODE4(NODE41))
+    let node6_key = scene.insert_under_in_transaction(node2_key, RSGNode::new(), t);
+    assert_valid(scene);
+    [node4_key, node41_key, node5_key, node6_key]
+}
+
+#[test]
+fn scene_transaction_commit_and_observe() {
+    let mut scene = TestScene::new();
+    let keys = build_transaction_test_scene(&mut scene);
+    let [root_key, node1_key, node2_key, node3_key, node11_key] = keys;
+
+    scene.set_observer(TestObserver::new());
+
+    let mut t = RSGSceneTransaction::new();
+    let [node4_key, node41_key, node5_key, node6_key] = fill_transaction(&mut scene, &keys, &mut t);
+    assert!(!t.is_empty());
+    assert!(!scene.is_valid(node1_key) && !scene.is_valid(node3_key));
+    assert!(scene.node_count() == 9);
+    assert!(scene.take_observer().unwrap().events.is_empty());
+
+    scene.set_observer(TestObserver::new());
+    let component_links = scene.commit_transaction(t);
+    assert_valid(&scene);
+    assert!(component_links.len() == 2);
+    assert!(scene.node_count() == 7);
+
+    let obs = scene.take_observer().unwrap();
+    assert!(obs.events == [
+        RSGEvent::SubtreeAboutToBeTemporarilyDetached(node11_key),
+        RSGEvent::SubtreeAboutToBeRemoved(node1_key),
+        RSGEvent::SubtreeAboutToBeRemoved(node3_key),
+        RSGEvent::SubtreeAddedOrReattached(node4_key),
+        RSGEvent::SubtreeAddedOrReattached(node5_key),
+        RSGEvent::SubtreeAddedOrReattached(node6_key)
+    ]);
+
+    // key, parent, first_child, last_child, prev_sibling, next_sibling
+    assert!(scene[root_key].links() == (Some(root_key), None, Some(node5_key), Some(node4_key), None, None));
+    assert!(scene[node5_key].links() == (Some(node5_key), Some(root_key), None, None, None, Some(node2_key)));
+    assert!(scene[node2_key].links() == (Some(node2_key), Some(root_key), Some(node6_key), Some(node6_key), Some(node5_key), Some(node4_key)));
+    assert!(scene[node6_key].links() == (Some(node6_key), Some(node2_key), Some(node11_key), Some(node11_key), None, None));
+    assert!(scene[node11_key].links() == (Some(node11_key), Some(node6_key), None, None, None, None));
+    assert!(scene[node4_key].links() == (Some(node4_key), Some(root_key), Some(node41_key), Some(node41_key), Some(node2_key), None));
+    assert!(scene[node41_key].links() == (Some(node41_key), Some(node4_key), None, None, None, None));
+}
+
+#[test]
+fn scene_transaction_rollback() {
+    let mut scene = TestScene::new();
+    let keys = build_transaction_test_scene(&mut scene);
+    let links_before: Vec<_> = keys.iter().map(|key| scene[*key].links()).collect();
+
+    scene.set_observer(TestObserver::new());
+
+    let mut t = RSGSceneTransaction::new();
+    let new_keys = fill_transaction(&mut scene, &keys, &mut t);
+    let component_links = scene.rollback_transaction(t);
+    assert_valid(&scene);
+    assert!(component_links.len() == 4);
+    assert!(scene.node_count() == 5);
+    for key in &keys {
+        assert!(scene.is_valid(*key));
+    }
+    for key in &new_keys {
+        assert!(!scene.is_valid(*key));
+    }
+    let links_after: Vec<_> = keys.iter().map(|key| scene[*key].links()).collect();
+    assert!(links_before == links_after);
+
+    let obs = scene.take_observer().unwrap();
+    assert!(obs.events.is_empty());
+}
+
 #[test]
 fn traversal() {
     let mut scene = TestScene::new();
     let root_key = scene.set_root(RSGNode::new());
+    assert_valid(&scene);
     for (node_key, depth) in scene.traverse(root_key) {
         assert!(node_key == root_key);
         assert!(depth == 0);
@@ -652,6 +1047,7 @@ fn traversal() {
 
     // ROOT(NODE1(NODE11, NODE12), NODE2(NODE21))
     let node1_key = scene.append(root_key, RSGNode::new());
+    assert_valid(&scene);
     for (node_key, depth) in scene.traverse(root_key) {
         match depth {
             0 => assert!(node_key == root_key),
@@ -666,9 +1062,13 @@ fn traversal() {
         }
     }
     let node2_key = scene.append(root_key, RSGNode::new());
+    assert_valid(&scene);
     let node11_key = scene.append(node1_key, RSGNode::new());
+    assert_valid(&scene);
     let node12_key = scene.append(node1_key, RSGNode::new());
+    assert_valid(&scene);
     let node21_key = scene.append(node2_key, RSGNode::new());
+    assert_valid(&scene);
 
     let mut n = 0;
     let expected = [root_key, node1_key, node11_key, node12_key, node2_key, node21_key];
@@ -703,17 +1103,263 @@ fn traversal() {
     }
 }
 
+#[test]
+fn traversal_breadth_first() {
+    let mut scene = TestScene::new();
+    let root_key = scene.set_root(RSGNode::new());
+    assert_valid(&scene);
+    for (node_key, depth) in scene.traverse_breadth_first(root_key) {
+        assert!(node_key == root_key);
+        assert!(depth == 0);
+    }
+
+    // ROOT(NODE1(NODE11, NODE12), NODE2(NODE21))
+    let node1_key = scene.append(root_key, RSGNode::new());
+    assert_valid(&scene);
+    let node2_key = scene.append(root_key, RSGNode::new());
+    assert_valid(&scene);
+    let node11_key = scene.append(node1_key, RSGNode::new());
+    assert_valid(&scene);
+    let node12_key = scene.append(node1_key, RSGNode::new());
+    assert_valid(&scene);
+    let node21_key = scene.append(node2_key, RSGNode::new());
+    assert_valid(&scene);
+
+    let mut n = 0;
+    let expected = [root_key, node1_key, node2_key, node11_key, node12_key, node21_key];
+    let expected_depth = [0, 1, 1, 2, 2, 2];
+    for (node_key, depth) in scene.traverse_breadth_first(root_key) {
+        assert!(node_key == expected[n]);
+        assert!(depth == expected_depth[n]);
+        n += 1;
+    }
+    assert!(n == expected.len());
+
+    let mut n = 0;
+    let expected = [node1_key, node11_key, node12_key];
+    let expected_depth = [0, 1, 1];
+    for (node_key, depth) in scene.traverse_breadth_first(node1_key) {
+        assert!(node_key == expected[n]);
+        assert!(depth == expected_depth[n]);
+        n += 1;
+    }
+    assert!(n == expected.len());
+}
+
+#[test]
+fn traversal_post_order() {
+    let mut scene = TestScene::new();
+    let root_key = scene.set_root(RSGNode::new());
+    assert_valid(&scene);
+    for (node_key, depth) in scene.traverse_post_order(root_key) {
+        assert!(node_key == root_key);
+        assert!(depth == 0);
+    }
+
+    // ROOT(NODE1(NODE11, NODE12), NODE2(NODE21))
+    let node1_key = scene.append(root_key, RSGNode::new());
+    assert_valid(&scene);
+    let node2_key = scene.append(root_key, RSGNode::new());
+    assert_valid(&scene);
+    let node11_key = scene.append(node1_key, RSGNode::new());
+    assert_valid(&scene);
+    let node12_key = scene.append(node1_key, RSGNode::new());
+    assert_valid(&scene);
+    let node21_key = scene.append(node2_key, RSGNode::new());
+    assert_valid(&scene);
+
+    let mut n = 0;
+    let expected = [node11_key, node12_key, node1_key, node21_key, node2_key, root_key];
+    let expected_depth = [2, 2, 1, 2, 1, 0];
+    for (node_key, depth) in scene.traverse_post_order(root_key) {
+        assert!(node_key == expected[n]);
+        assert!(depth == expected_depth[n]);
+        n += 1;
+    }
+    assert!(n == expected.len());
+
+    let mut n = 0;
+    let expected = [node21_key, node2_key];
+    let expected_depth = [1, 0];
+    for (node_key, depth) in scene.traverse_post_order(node2_key) {
+        assert!(node_key == expected[n]);
+        assert!(depth == expected_depth[n]);
+        n += 1;
+    }
+    assert!(n == expected.len());
+
+    let mut n = 0;
+    for (node_key, depth) in scene.traverse_post_order(node11_key) {
+        assert!(node_key == node11_key);
+        assert!(depth == 0);
+        n += 1;
+    }
+    assert!(n == 1);
+}
+
+#[test]
+fn traversal_reverse() {
+    let mut scene = TestScene::new();
+    let root_key = scene.set_root(RSGNode::new());
+    assert_valid(&scene);
+    for (node_key, depth) in scene.traverse_reverse(root_key) {
+        assert!(node_key == root_key);
+        assert!(depth == 0);
+    }
+
+    // ROOT(NODE1(NODE11, NODE12), NODE2(NODE21))
+    let node1_key = scene.append(root_key, RSGNode::new());
+    assert_valid(&scene);
+    let node2_key = scene.append(root_key, RSGNode::new());
+    assert_valid(&scene);
+    let node11_key = scene.append(node1_key, RSGNode::new());
+    assert_valid(&scene);
+    let node12_key = scene.append(node1_key, RSGNode::new());
+    assert_valid(&scene);
+    let node21_key = scene.append(node2_key, RSGNode::new());
+    assert_valid(&scene);
+
+    let mut n = 0;
+    let expected = [node21_key, node2_key, node12_key, node11_key, node1_key, root_key];
+    let expected_depth = [2, 1, 2, 2, 1, 0];
+    for (node_key, depth) in scene.traverse_reverse(root_key) {
+        assert!(node_key == expected[n]);
+        assert!(depth == expected_depth[n]);
+        n += 1;
+    }
+    assert!(n == expected.len());
+
+    let forward: Vec<(RSGNodeKey, u32)> = scene.traverse(node1_key).collect();
+    let mut reverse: Vec<(RSGNodeKey, u32)> = scene.traverse_reverse(node1_key).collect();
+    reverse.reverse();
+    assert!(forward == reverse);
+}
+
+#[test]
+fn visit_with_pruning() {
+    let mut scene = TestScene::new();
+    // ROOT(NODE1(NODE11(NODE111), NODE12), NODE2(NODE21))
+    let root_key = scene.set_root(RSGNode::new());
+    assert_valid(&scene);
+    let node1_key = scene.append(root_key, RSGNode::new());
+    assert_valid(&scene);
+    let node2_key = scene.append(root_key, RSGNode::new());
+    assert_valid(&scene);
+    let node11_key = scene.append(node1_key, RSGNode::new());
+    assert_valid(&scene);
+    let _node111_key = scene.append(node11_key, RSGNode::new());
+    assert_valid(&scene);
+    let node12_key = scene.append(node1_key, RSGNode::new());
+    assert_valid(&scene);
+    let node21_key = scene.append(node2_key, RSGNode::new());
+    assert_valid(&scene);
+
+    let mut visited = vec![];
+    scene.visit(root_key, |key, depth| {
+        visited.push((key, depth));
+        if key == node11_key { RSGVisitResult::SkipChildren } else { RSGVisitResult::Continue }
+    });
+    assert!(visited == [(root_key, 0), (node1_key, 1), (node11_key, 2), (node12_key, 2), (node2_key, 1), (node21_key, 2)]);
+
+    let mut visited = vec![];
+    scene.visit(root_key, |key, _| {
+        visited.push(key);
+        if key == node1_key { RSGVisitResult::SkipChildren } else { RSGVisitResult::Continue }
+    });
+    assert!(visited == [root_key, node1_key, node2_key, node21_key]);
+
+    let mut visited = vec![];
+    scene.visit(root_key, |key, _| {
+        visited.push(key);
+        if key == node12_key { RSGVisitResult::Stop } else { RSGVisitResult::Continue }
+    });
+    assert!(visited.len() == 5 && *visited.last().unwrap() == node12_key);
+
+    let mut visited = vec![];
+    scene.visit(node1_key, |key, _| {
+        visited.push(key);
+        RSGVisitResult::SkipChildren
+    });
+    assert!(visited == [node1_key]);
+
+    let mut visited = vec![];
+    let mut it = scene.traverse(root_key);
+    it.skip_children(); // no effect before the first next()
+    while let Some((key, _)) = it.next() {
+        visited.push(key);
+        if key == node1_key || key == node21_key {
+            it.skip_children();
+        }
+    }
+    assert!(visited == [root_key, node1_key, node2_key, node21_key]);
+}
+
+#[test]
+fn children_and_siblings() {
+    let mut scene = TestScene::new();
+    // ROOT(NODE1(NODE11, NODE12, NODE13), NODE2)
+    let root_key = scene.set_root(RSGNode::new());
+    assert_valid(&scene);
+    let node1_key = scene.append(root_key, RSGNode::new());
+    assert_valid(&scene);
+    let node2_key = scene.append(root_key, RSGNode::new());
+    assert_valid(&scene);
+    let node11_key = scene.append(node1_key, RSGNode::new());
+    assert_valid(&scene);
+    let node12_key = scene.append(node1_key, RSGNode::new());
+    assert_valid(&scene);
+    let node13_key = scene.append(node1_key, RSGNode::new());
+    assert_valid(&scene);
+
+    assert!(scene.children(root_key).collect::<Vec<_>>() == [node1_key, node2_key]);
+    assert!(scene.children(node1_key).collect::<Vec<_>>() == [node11_key, node12_key, node13_key]);
+    assert!(scene.children_rev(node1_key).collect::<Vec<_>>() == [node13_key, node12_key, node11_key]);
+    assert!(scene.children(node2_key).next().is_none());
+    assert!(scene.children_rev(node2_key).next().is_none());
+
+    assert!(scene.siblings(node12_key).collect::<Vec<_>>() == [node11_key, node13_key]);
+    assert!(scene.siblings(node1_key).collect::<Vec<_>>() == [node2_key]);
+    assert!(scene.siblings(root_key).next().is_none());
+
+    assert!(scene.child_count(root_key) == 2);
+    assert!(scene.child_count(node1_key) == 3);
+    assert!(scene.child_count(node2_key) == 0);
+
+    assert!(scene.nth_child(node1_key, 0) == Some(node11_key));
+    assert!(scene.nth_child(node1_key, 2) == Some(node13_key));
+    assert!(scene.nth_child(node1_key, 3).is_none());
+
+    assert!(scene.index_in_parent(root_key).is_none());
+    assert!(scene.index_in_parent(node2_key) == Some(1));
+    assert!(scene.index_in_parent(node13_key) == Some(2));
+
+    assert!(scene.first_child(node1_key) == Some(node11_key));
+    assert!(scene.last_child(node1_key) == Some(node13_key));
+    assert!(scene.first_child(node2_key).is_none() && scene.last_child(node2_key).is_none());
+    assert!(scene.next_sibling(node11_key) == Some(node12_key));
+    assert!(scene.next_sibling(node13_key).is_none());
+    assert!(scene.prev_sibling(node12_key) == Some(node11_key));
+    assert!(scene.prev_sibling(node11_key).is_none());
+}
+
 #[test]
 fn visit_ancestors() {
     let mut scene = TestScene::new();
     // ROOT(NODE1(NODE11, NODE12), NODE2(NODE21(NODE22)))
     let root_key = scene.set_root(RSGNode::new());
+    assert_valid(&scene);
     let node1_key = scene.append(root_key, RSGNode::new());
+    assert_valid(&scene);
     let node2_key = scene.append(root_key, RSGNode::new());
+    assert_valid(&scene);
     let _node11_key = scene.append(node1_key, RSGNode::new());
+    assert_valid(&scene);
     let node12_key = scene.append(node1_key, RSGNode::new());
+    assert_valid(&scene);
     let node21_key = scene.append(node2_key, RSGNode::new());
+    assert_valid(&scene);
     let node22_key = scene.append(node21_key, RSGNode::new());
+    assert_valid(&scene);
 
     for node_key in scene.ancestors_with_node(root_key) {
         assert!(node_key == root_key);
@@ -744,12 +1390,79 @@ fn visit_ancestors() {
     }
 }
 
+#[test]
+fn remove_and_collect_component_links() {
+    let mut scene = TestScene::new();
+    // ROOT(NODE1(NODE11(NODE111), NODE12), NODE2(NODE21))
+    let root_key = scene.set_root(RSGNode::new());
+    assert_valid(&scene);
+    let mut handle = 0;
+    let mut make_node = || {
+        handle += 1;
+        RSGNode::with_component_links(TestCompLinks {
+            transform_handle: Some(handle),
+            geometry_handle: None,
+            material_handle: None
+        })
+    };
+    let node1_key = scene.append(root_key, make_node());
+    assert_valid(&scene);
+    let node2_key = scene.append(root_key, make_node());
+    assert_valid(&scene);
+    let node11_key = scene.append(node1_key, make_node());
+    assert_valid(&scene);
+    let _node111_key = scene.append(node11_key, make_node());
+    assert_valid(&scene);
+    let _node12_key = scene.append(node1_key, make_node());
+    assert_valid(&scene);
+    let _node21_key = scene.append(node2_key, make_node());
+    assert_valid(&scene);
+    assert!(scene.node_count() == 7);
+
+    scene.set_observer(TestObserver::new());
+
+    // ROOT(NODE2(NODE21))
+    let mut handles = vec![];
+    scene.remove_with_callback(node1_key, |c| handles.push(c.transform_handle.unwrap()));
+    assert_valid(&scene);
+    handles.sort();
+    assert!(handles == [1, 3, 4, 5]);
+    assert!(scene.node_count() == 3);
+
+    let obs = scene.take_observer().unwrap();
+    assert!(obs.events.len() == 1);
+    assert!(obs.events[0] == RSGEvent::SubtreeAboutToBeRemoved(node1_key));
+
+    // ROOT
+    let mut handles = vec![];
+    scene.clear_with_callback(|c| handles.push(c.transform_handle.unwrap()));
+    assert_valid(&scene);
+    handles.sort();
+    assert!(handles == [2, 6]);
+    assert!(scene.node_count() == 1);
+
+    let mut t = RSGSubtreeAddTransaction::new();
+    let node3_key = scene.append_with_transaction(root_key, make_node(), &mut t);
+    assert_valid(&scene);
+    scene.append_with_transaction(node3_key, make_node(), &mut t);
+    assert_valid(&scene);
+    let component_links = scene.rollback(t);
+    assert_valid(&scene);
+    assert!(component_links.len() == 2);
+    assert!(component_links[0].transform_handle == Some(7));
+    assert!(component_links[1].transform_handle == Some(8));
+    assert!(scene.node_count() == 1);
+}
+
 #[test]
 fn component_links() {
     let mut scene = TestScene::new();
     let root_key = scene.set_root(RSGNode::new());
+    assert_valid(&scene);
     let node1_key = scene.append(root_key, RSGNode::new());
+    assert_valid(&scene);
     let _node2_key = scene.append(root_key, RSGNode::new());
+    assert_valid(&scene);
     // ROOT(NODE1, NODE2)
 
     {
@@ -766,6 +1479,7 @@ fn component_links() {
     }
 
     let _node3_key = scene.prepend(root_key, RSGNode::new());
+    assert_valid(&scene);
     // ROOT(NODE3, NODE1, NODE2)
 
     {
@@ -783,18 +1497,21 @@ fn remove_and_add_new_with_same_component_links() {
     let mut scene = TestScene::new();
     // ROOT(NODE1, NODE2)
     let root_key = scene.set_root(RSGNode::new());
+    assert_valid(&scene);
     let c = TestCompLinks {
         transform_handle: Some(1),
         geometry_handle: Some(2),
         material_handle: Some(3)
     };
     let node1_key = scene.append(root_key, RSGNode::with_component_links(c));
+    assert_valid(&scene);
     let c = TestCompLinks {
         transform_handle: Some(4),
         geometry_handle: Some(5),
         material_handle: Some(6)
     };
     let node2_key = scene.append(root_key, RSGNode::with_component_links(c));
+    assert_valid(&scene);
 
     assert!(scene.node_count() == 3);
     assert!(scene.get_component_links(node1_key).transform_handle == Some(1));
@@ -802,12 +1519,14 @@ fn remove_and_add_new_with_same_component_links() {
 
     // ROOT(NODE2)
     let c = scene.remove(node1_key);
+    assert_valid(&scene);
     assert!(scene.node_count() == 2);
     assert!(!scene.is_valid(node1_key));
     assert!(c.transform_handle == Some(1));
 
     // ROOT(NODE2, NODE3)
     let node3_key = scene.append(root_key, RSGNode::with_component_links(c));
+    assert_valid(&scene);
 
     assert!(scene.node_count() == 3);
     assert!(!scene.is_valid(node1_key));
@@ -826,3 +1545,430 @@ fn remove_and_add_new_with_same_component_links() {
     assert!(scene[node2_key].links() == (Some(node2_key), Some(root_key), None, None, None, Some(node3_key)));
     assert!(scene[node3_key].links() == (Some(node3_key), Some(root_key), None, None, Some(node2_key), None));
 }
+
+#[test]
+fn fallible_operations() {
+    let mut scene = TestScene::new();
+    assert!(scene.try_root() == Err(RSGSceneError::NoRoot));
+    let root_key = scene.try_set_root(RSGNode::new()).unwrap();
+    assert_valid(&scene);
+    assert!(scene.try_set_root(RSGNode::new()) == Err(RSGSceneError::RootAlreadySet));
+
+    // ROOT(NODE1(NODE11), NODE2)
+    let node1_key = scene.try_append(root_key, RSGNode::new()).unwrap();
+    assert_valid(&scene);
+    let node11_key = scene.try_append(node1_key, RSGNode::new()).unwrap();
+    assert_valid(&scene);
+    let node2_key = scene.try_insert(root_key, RSGNode::new(), RSGNodePosition::After(node1_key)).unwrap();
+    assert_valid(&scene);
+    assert!(scene.child_count(root_key) == 2);
+
+    // the root cannot be removed or have siblings
+    assert!(scene.try_remove(root_key) == Err(RSGSceneError::RootNotAllowed(root_key)));
+    assert!(scene.try_insert_before(root_key, RSGNode::new()) == Err(RSGSceneError::RootNotAllowed(root_key)));
+    assert!(scene.try_insert_after(root_key, RSGNode::new()) == Err(RSGSceneError::RootNotAllowed(root_key)));
+    assert!(scene.try_move_subtree(root_key, node1_key, RSGNodePosition::Append) == Err(RSGSceneError::RootNotAllowed(root_key)));
+
+    // positions must refer to children of the parent
+    assert!(scene.try_insert(node1_key, RSGNode::new(), RSGNodePosition::Before(node2_key)) == Err(RSGSceneError::NotAChild(node2_key, node1_key)));
+    assert!(scene.try_move_subtree(node1_key, node11_key, RSGNodePosition::Append) == Err(RSGSceneError::MoveIntoOwnSubtree(node1_key, node11_key)));
+
+    // already linked nodes cannot be inserted again
+    let linked_node = scene[node1_key];
+    assert!(scene.try_append(node2_key, linked_node) == Err(RSGSceneError::NodeNotClean));
+
+    // stale keys
+    let c = scene.try_remove(node1_key).unwrap();
+    assert_valid(&scene);
+    assert!(c.transform_handle.is_none());
+    assert!(scene.try_get(node1_key).is_err());
+    assert!(scene.try_get_component_links(node11_key) == Err(RSGSceneError::InvalidNode(node11_key)));
+    assert!(scene.try_append(node1_key, RSGNode::new()) == Err(RSGSceneError::InvalidNode(node1_key)));
+    assert!(scene.try_insert_before(node11_key, RSGNode::new()) == Err(RSGSceneError::InvalidNode(node11_key)));
+    assert!(scene.try_remove(node1_key) == Err(RSGSceneError::InvalidNode(node1_key)));
+    assert!(scene.try_move_subtree(node2_key, node1_key, RSGNodePosition::Append) == Err(RSGSceneError::InvalidNode(node1_key)));
+    assert!(scene.node_count() == 2);
+
+    scene.try_get_component_links_mut(node2_key).unwrap().transform_handle = Some(7);
+    assert!(scene.try_get_component_links(node2_key).unwrap().transform_handle == Some(7));
+}
+
+#[test]
+fn validate_reports_violations() {
+    let mut scene = TestScene::new();
+    let root_key = scene.set_root(RSGNode::new());
+    assert_valid(&scene);
+
+    // ROOT(NODE1(NODE11), NODE2)
+    let node1_key = scene.append(root_key, RSGNode::new());
+    let node11_key = scene.append(node1_key, RSGNode::new());
+    let node2_key = scene.append(root_key, RSGNode::new());
+    assert_valid(&scene);
+
+    // NODE11 claims to be a child of NODE2 while it is in the child list of NODE1
+    scene[node11_key].parent_key = Some(node2_key);
+    let violations = scene.validate().unwrap_err();
+    assert!(violations == vec![RSGSceneViolation::ParentMismatch(node11_key, node1_key)]);
+    scene[node11_key].parent_key = Some(node1_key);
+    assert_valid(&scene);
+
+    // wrong key field
+    scene[node2_key].key = Some(node1_key);
+    let violations = scene.validate().unwrap_err();
+    assert!(violations == vec![RSGSceneViolation::KeyMismatch(node2_key, Some(node1_key))]);
+    scene[node2_key].key = Some(node2_key);
+
+    // a parent pointing to a removed node
+    let node3_key = scene.append(root_key, RSGNode::new());
+    scene.remove(node3_key);
+    scene[node11_key].parent_key = Some(node3_key);
+    let violations = scene.validate().unwrap_err();
+    assert!(violations == vec![RSGSceneViolation::DanglingLink(node11_key, RSGNodeLink::Parent, node3_key)]);
+    scene[node11_key].parent_key = Some(node1_key);
+    assert_valid(&scene);
+
+    // nodes pending in a transaction are fine
+    let mut t = RSGSubtreeAddTransaction::new();
+    let pending_key = scene.append_with_transaction(node2_key, RSGNode::new(), &mut t);
+    scene.append_with_transaction(pending_key, RSGNode::new(), &mut t);
+    assert_valid(&scene);
+
+    // but a parentless node that is neither pending nor detached is an orphan
+    scene[pending_key].key = Some(pending_key);
+    let violations = scene.validate().unwrap_err();
+    assert!(violations == vec![RSGSceneViolation::Unreachable(pending_key)]);
+    scene[pending_key].key = None;
+    scene.rollback(t);
+    assert_valid(&scene);
+}
+
+#[test]
+fn detach_and_reattach() {
+    let mut scene = TestScene::new();
+    let root_key = scene.set_root(RSGNode::new());
+
+    // ROOT(NODE1(NODE11, NODE12), NODE2)
+    let node1_key = scene.append(root_key, RSGNode::new());
+    let node11_key = scene.append(node1_key, RSGNode::new());
+    let node12_key = scene.append(node1_key, RSGNode::new());
+    let node2_key = scene.append(root_key, RSGNode::new());
+    scene.set_observer(TestObserver::new());
+
+    // ROOT(NODE2), NODE1(NODE11, NODE12)
+    let detached = scene.detach(node1_key);
+    assert_valid(&scene);
+    assert!(detached.node_key() == node1_key);
+    assert!(scene.node_count() == 5);
+    assert!(!scene.is_valid(node1_key) && scene.is_detached(node1_key));
+    assert!(!scene.is_valid(node11_key) && !scene.is_valid(node12_key) && !scene.is_reachable(node11_key));
+    let keys: Vec<RSGNodeKey> = scene.traverse(root_key).map(|(key, _)| key).collect();
+    assert!(keys == vec![root_key, node2_key]);
+    let children: Vec<RSGNodeKey> = scene.children(node1_key).collect();
+    assert!(children == vec![node11_key, node12_key]);
+    assert!(scene.try_append(node1_key, RSGNode::new()) == Err(RSGSceneError::DetachedNode(node1_key)));
+
+    // ROOT(NODE2(NODE1(NODE11, NODE12)))
+    let (e, detached) = scene.try_reattach(detached, node1_key, RSGNodePosition::Append).unwrap_err();
+    assert!(e == RSGSceneError::DetachedNode(node1_key));
+    let (e, detached) = scene.try_reattach(detached, node11_key, RSGNodePosition::Append).unwrap_err();
+    assert!(e == RSGSceneError::DetachedNode(node11_key));
+    assert!(scene.reattach(detached, node2_key, RSGNodePosition::Append) == node1_key);
+    assert_valid(&scene);
+    assert!(scene.is_valid(node11_key) && scene.is_valid(node12_key) && !scene.is_detached(node1_key));
+    let keys: Vec<RSGNodeKey> = scene.traverse(root_key).map(|(key, _)| key).collect();
+    assert!(keys == vec![root_key, node2_key, node1_key, node11_key, node12_key]);
+
+    let obs = scene.take_observer().unwrap();
+    assert!(obs.events == [RSGEvent::SubtreeAboutToBeTemporarilyDetached(node1_key), RSGEvent::SubtreeAddedOrReattached(node1_key)]);
+    scene.set_observer(TestObserver::new());
+
+    // ROOT(NODE2)
+    let detached: RSGDetachedSubtree = scene.try_detach(node1_key).unwrap();
+    assert!(scene.try_detach(root_key).unwrap_err() == RSGSceneError::RootNotAllowed(root_key));
+    let c = scene.remove_detached(detached);
+    assert!(c.len() == 3);
+    assert!(scene.node_count() == 2);
+    assert_valid(&scene);
+    let obs = scene.take_observer().unwrap();
+    assert!(obs.events == [RSGEvent::SubtreeAboutToBeTemporarilyDetached(node1_key)]);
+}
+
+#[test]
+fn try_rejects_detached_descendants() {
+    let mut scene = TestScene::new();
+    let root_key = scene.set_root(RSGNode::new());
+
+    // ROOT(NODE2), NODE1(NODE11(NODE111), NODE12)
+    let node1_key = scene.append(root_key, RSGNode::new());
+    let node11_key = scene.append(node1_key, RSGNode::new());
+    let node111_key = scene.append(node11_key, RSGNode::new());
+    let node12_key = scene.append(node1_key, RSGNode::new());
+    let node2_key = scene.append(root_key, RSGNode::new());
+    let detached = scene.detach(node1_key);
+    scene.set_observer(TestObserver::new());
+
+    assert!(scene.try_get(node11_key).err() == Some(RSGSceneError::DetachedNode(node11_key)));
+    assert!(scene.try_get_component_links_mut(node111_key).err() == Some(RSGSceneError::DetachedNode(node111_key)));
+    assert!(scene.try_append(node11_key, RSGNode::new()) == Err(RSGSceneError::DetachedNode(node11_key)));
+    assert!(scene.try_insert_after(node12_key, RSGNode::new()) == Err(RSGSceneError::DetachedNode(node12_key)));
+    assert!(scene.try_insert(node11_key, RSGNode::new(), RSGNodePosition::Prepend) == Err(RSGSceneError::DetachedNode(node11_key)));
+    assert!(scene.try_move_subtree(node2_key, node111_key, RSGNodePosition::Append) == Err(RSGSceneError::DetachedNode(node111_key)));
+    assert!(scene.try_move_subtree(node11_key, root_key, RSGNodePosition::Append) == Err(RSGSceneError::DetachedNode(node11_key)));
+    assert!(scene.try_move_to_index(node12_key, 0) == Err(RSGSceneError::DetachedNode(node12_key)));
+    assert!(scene.try_swap_siblings(node11_key, node12_key) == Err(RSGSceneError::DetachedNode(node11_key)));
+    assert!(scene.try_remove(node11_key) == Err(RSGSceneError::DetachedNode(node11_key)));
+    assert!(scene.try_remove_children(node1_key) == Err(RSGSceneError::DetachedNode(node1_key)));
+    assert!(scene.try_detach(node11_key).unwrap_err() == RSGSceneError::DetachedNode(node11_key));
+    assert!(scene.try_mark_dirty(node111_key, RSGDirtyFlags::TRANSFORM) == Err(RSGSceneError::DetachedNode(node111_key)));
+    let (e, detached) = scene.try_reattach(detached, node12_key, RSGNodePosition::Append).unwrap_err();
+    assert!(e == RSGSceneError::DetachedNode(node12_key));
+
+    // nothing changed and nothing was reported
+    assert_valid(&scene);
+    assert!(scene.node_count() == 6);
+    assert!(scene[node2_key].parent_key == Some(root_key) && scene.children(node2_key).next().is_none());
+    let children: Vec<RSGNodeKey> = scene.children(node1_key).collect();
+    assert!(children == vec![node11_key, node12_key]);
+    assert!(scene.take_observer().unwrap().events.is_empty());
+
+    // removed nodes are still just invalid
+    scene.remove_detached(detached);
+    assert!(scene.try_append(node11_key, RSGNode::new()) == Err(RSGSceneError::InvalidNode(node11_key)));
+}
+
+#[test]
+fn names_and_paths() {
+    let mut scene = TestScene::new();
+    let root_key = scene.set_root(RSGNode::new());
+    scene.set_name(root_key, "root");
+
+    // root(ui(toolbar(button1, button2, #2), panel(button1)), #1)
+    let ui_key = scene.append(root_key, RSGNode::new());
+    scene.set_name(ui_key, "ui");
+    let other_key = scene.append(root_key, RSGNode::new());
+    let toolbar_key = scene.append(ui_key, RSGNode::new());
+    scene.set_name(toolbar_key, "toolbar");
+    let button1_key = scene.append(toolbar_key, RSGNode::new());
+    scene.set_name(button1_key, "button1");
+    let button2_key = scene.append(toolbar_key, RSGNode::new());
+    scene.set_name(button2_key, "button2");
+    let unnamed_key = scene.append(toolbar_key, RSGNode::new());
+    let panel_key = scene.append(ui_key, RSGNode::new());
+    scene.set_name(panel_key, "panel");
+    let panel_button_key = scene.append(panel_key, RSGNode::new());
+    scene.set_name(panel_button_key, "button1");
+
+    assert!(scene.name(toolbar_key) == Some("toolbar") && scene.name(other_key).is_none());
+    let mut found = scene.find_by_name("button1");
+    found.sort();
+    let mut expected = vec![button1_key, panel_button_key];
+    expected.sort();
+    assert!(found.as_slice() == expected.as_slice());
+    assert!(scene.find_by_name("nothing").is_empty());
+
+    assert!(scene.path_of(button2_key) == "/root/ui/toolbar/button2");
+    assert!(scene.path_of(unnamed_key) == "/root/ui/toolbar/#2");
+    assert!(scene.path_of(other_key) == "/root/#1");
+    for key in [root_key, button2_key, unnamed_key, other_key, panel_key].iter() {
+        assert!(scene.find_by_path(&scene.path_of(*key)) == vec![*key]);
+    }
+
+    // relative, wildcards
+    assert!(scene.find_by_path("ui/toolbar/button1") == vec![button1_key]);
+    assert!(scene.find_by_path_from(toolbar_key, "../panel/button1") == vec![panel_button_key]);
+    assert!(scene.find_by_path_from(toolbar_key, "./button2") == vec![button2_key]);
+    assert!(scene.find_by_path("ui/toolbar/button?") == vec![button1_key, button2_key]);
+    assert!(scene.find_by_path("ui/toolbar/*") == vec![button1_key, button2_key, unnamed_key]);
+    assert!(scene.find_by_path("ui/*/button1") == vec![button1_key, panel_button_key]);
+    assert!(scene.find_by_path("/root/**/button1") == vec![button1_key, panel_button_key]);
+    assert!(scene.find_by_path("/*/ui") == vec![ui_key]);
+    assert!(scene.find_by_path("/other").is_empty());
+    assert!(scene.find_by_path("ui/nothing/button1").is_empty());
+    assert!(scene.find_by_path("ui/*o*l*x*r").is_empty());
+    assert!(scene.find_by_path("ui/t*o*l*a*r") == vec![toolbar_key]);
+    assert!(scene.find_by_path("ui/**r") == vec![toolbar_key]);
+
+    // no exponential backtracking with many '*'
+    let long_key = scene.append(root_key, RSGNode::new());
+    scene.set_name(long_key, &"a".repeat(200));
+    assert!(scene.find_by_path(&format!("{}b", "*a".repeat(30))).is_empty());
+    assert!(scene.find_by_path(&format!("{}a", "*a".repeat(30))) == vec![long_key]);
+    scene.remove(long_key);
+
+    // invalid names are rejected by try_set_name
+    assert!(scene.try_set_name(other_key, "a/b") == Err(RSGSceneError::InvalidName));
+    assert!(scene.try_set_name(other_key, "#1") == Err(RSGSceneError::InvalidName));
+    assert!(scene.try_set_name(other_key, "..") == Err(RSGSceneError::InvalidName));
+    assert!(scene.name(other_key).is_none());
+    assert!(scene.try_set_name(other_key, "other") == Ok(()));
+    assert!(scene.find_by_path("/root/other") == vec![other_key]);
+    scene.clear_name(other_key);
+
+    // renaming and removal keep the index up to date
+    scene.set_name(panel_button_key, "close");
+    assert!(scene.find_by_name("button1").as_slice() == [button1_key]);
+    assert!(scene.find_by_path("ui/panel/close") == vec![panel_button_key]);
+    scene.remove(panel_key);
+    assert!(scene.find_by_name("close").is_empty() && scene.find_by_name("panel").is_empty());
+    scene.clear_name(button1_key);
+    assert!(scene.find_by_name("button1").is_empty());
+    assert!(scene.path_of(button1_key) == "/root/ui/toolbar/#0");
+
+    // nodes in detached subtrees are not found
+    let detached = scene.detach(toolbar_key);
+    assert!(scene.find_by_name("button2").is_empty());
+    assert!(scene.find_by_path("ui/toolbar").is_empty());
+    scene.reattach(detached, ui_key, RSGNodePosition::Append);
+    assert!(scene.find_by_name("button2").as_slice() == [button2_key]);
+}
+
+#[test]
+fn relationship_queries() {
+    let mut scene = TestScene::new();
+    let root_key = scene.set_root(RSGNode::new());
+
+    // ROOT(NODE1(NODE11(NODE111), NODE12), NODE2)
+    let node1_key = scene.append(root_key, RSGNode::new());
+    let node11_key = scene.append(node1_key, RSGNode::new());
+    let node111_key = scene.append(node11_key, RSGNode::new());
+    let node12_key = scene.append(node1_key, RSGNode::new());
+    let node2_key = scene.append(root_key, RSGNode::new());
+
+    assert!(scene.depth(root_key) == 0 && scene.depth(node1_key) == 1 && scene.depth(node111_key) == 3);
+    assert!(scene.is_ancestor_of(root_key, node111_key) && scene.is_ancestor_of(node1_key, node12_key));
+    assert!(!scene.is_ancestor_of(node12_key, node111_key) && !scene.is_ancestor_of(node1_key, node1_key));
+    assert!(!scene.is_ancestor_of(node111_key, node1_key));
+
+    assert!(scene.lowest_common_ancestor(node111_key, node12_key) == Some(node1_key));
+    assert!(scene.lowest_common_ancestor(node12_key, node111_key) == Some(node1_key));
+    assert!(scene.lowest_common_ancestor(node111_key, node2_key) == Some(root_key));
+    assert!(scene.lowest_common_ancestor(node11_key, node111_key) == Some(node11_key));
+    assert!(scene.lowest_common_ancestor(node2_key, node2_key) == Some(node2_key));
+
+    assert!(scene.path_between(node111_key, node12_key).unwrap().as_slice() == [node111_key, node11_key, node1_key, node12_key]);
+    assert!(scene.path_between(node2_key, node111_key).unwrap().as_slice() == [node2_key, root_key, node1_key, node11_key, node111_key]);
+    assert!(scene.path_between(node111_key, node1_key).unwrap().as_slice() == [node111_key, node11_key, node1_key]);
+    assert!(scene.path_between(node12_key, node12_key).unwrap().as_slice() == [node12_key]);
+
+    // different trees
+    let detached = scene.detach(node11_key);
+    assert!(scene.lowest_common_ancestor(node111_key, node12_key).is_none());
+    assert!(scene.path_between(node111_key, node2_key).is_none());
+    assert!(scene.depth(node111_key) == 1);
+    scene.reattach(detached, node1_key, RSGNodePosition::Prepend);
+}
+
+#[derive(Debug, PartialEq)]
+enum MirrorEvent {
+    Added(RSGNodeKey, RSGNodePlacement),
+    Detached(RSGNodeKey, RSGNodePlacement),
+    Removed(RSGNodeKey, RSGNodePlacement, Vec<Option<usize>>),
+    Reordered(RSGNodeKey, Vec<RSGNodeKey>)
+}
+
+#[derive(Default)]
+struct MirrorObserver {
+    wants_links: bool,
+    events: Vec<MirrorEvent>
+}
+
+impl MirrorObserver {
+    fn with_links() -> Self {
+        MirrorObserver {
+            wants_links: true,
+            ..Default::default()
+        }
+    }
+
+    fn set_on(self, scene: &mut TestScene) -> Arc<Mutex<MirrorObserver>> {
+        let mirror = Arc::new(Mutex::new(self));
+        scene.set_hierarchy_observer(Box::new(mirror.clone()));
+        mirror
+    }
+}
+
+impl RSGHierarchyObserver<TestCompLinks> for MirrorObserver {
+    fn notify_hierarchy(&mut self, event: RSGHierarchyEvent<TestCompLinks>) {
+        self.events.push(match event {
+            RSGHierarchyEvent::SubtreeAddedOrReattached(key, placement) => MirrorEvent::Added(key, placement),
+            RSGHierarchyEvent::SubtreeAboutToBeTemporarilyDetached(key, placement) => MirrorEvent::Detached(key, placement),
+            RSGHierarchyEvent::SubtreeAboutToBeRemoved(key, placement, links) =>
+                MirrorEvent::Removed(key, placement, links.iter().map(|c| c.transform_handle).collect()),
+            RSGHierarchyEvent::SiblingOrderChanged(parent_key, child_keys) => MirrorEvent::Reordered(parent_key, child_keys.to_vec())
+        });
+    }
+
+    fn wants_removed_component_links(&self) -> bool {
+        self.wants_links
+    }
+}
+
+fn placement(parent_key: RSGNodeKey, prev_sibling_key: Option<RSGNodeKey>, next_sibling_key: Option<RSGNodeKey>) -> RSGNodePlacement {
+    RSGNodePlacement {
+        parent_key: Some(parent_key),
+        prev_sibling_key,
+        next_sibling_key
+    }
+}
+
+fn node_with_handle(handle: usize) -> RSGNode<TestCompLinks> {
+    RSGNode::with_component_links(TestCompLinks {
+        transform_handle: Some(handle),
+        geometry_handle: None,
+        material_handle: None
+    })
+}
+
+#[test]
+fn hierarchy_events_with_placement() {
+    let mut scene = TestScene::new();
+    let root_key = scene.set_root(RSGNode::new());
+
+    // ROOT(NODE1(NODE11), NODE2)
+    let node1_key = scene.append(root_key, node_with_handle(1));
+    let node11_key = scene.append(node1_key, node_with_handle(11));
+    let node2_key = scene.append(root_key, node_with_handle(2));
+    scene.set_observer(TestObserver::new());
+    let mirror = MirrorObserver::with_links().set_on(&mut scene);
+
+    // ROOT(NODE2, NODE3, NODE1(NODE11))
+    let node3_key = scene.insert_after(node2_key, node_with_handle(3));
+    scene.move_subtree(node1_key, root_key, RSGNodePosition::Append);
+    // ROOT(NODE3, NODE1(NODE11), NODE2)
+    scene.swap_siblings(node2_key, node1_key);
+    scene.move_to_index(node1_key, 1);
+    // ROOT(NODE3, NODE2)
+    scene.remove(node1_key);
+
+    // both observers see the same changes
+    assert!(scene.take_observer().unwrap().events.len() == 6);
+    assert!(scene.take_hierarchy_observer().is_some());
+    assert!(mirror.lock().unwrap().events == vec![
+        MirrorEvent::Added(node3_key, placement(root_key, Some(node2_key), None)),
+        MirrorEvent::Detached(node1_key, placement(root_key, None, Some(node2_key))),
+        MirrorEvent::Added(node1_key, placement(root_key, Some(node3_key), None)),
+        MirrorEvent::Reordered(root_key, vec![node1_key, node3_key, node2_key]),
+        MirrorEvent::Reordered(root_key, vec![node3_key, node1_key, node2_key]),
+        MirrorEvent::Removed(node1_key, placement(root_key, Some(node3_key), Some(node2_key)), vec![Some(1), Some(11)])
+    ]);
+    assert!(!scene.is_valid(node11_key));
+
+    // transactions report the placement from before the transaction
+    let mirror = MirrorObserver::with_links().set_on(&mut scene);
+    let mut t = RSGSceneTransaction::new();
+    scene.move_subtree_in_transaction(node2_key, node3_key, RSGNodePosition::Append, &mut t);
+    scene.remove_in_transaction(node3_key, &mut t);
+    scene.commit_transaction(t);
+    // NODE2 moved into NODE3 first, but each node is reported once, from its original place
+    assert!(mirror.lock().unwrap().events == vec![
+        MirrorEvent::Removed(node3_key, placement(root_key, None, Some(node2_key)), vec![Some(3)]),
+        MirrorEvent::Removed(node2_key, placement(root_key, Some(node3_key), None), vec![Some(2)])
+    ]);
+
+    // the component links are only collected when asked for
+    let node4_key = scene.append(root_key, node_with_handle(4));
+    scene.append(node4_key, node_with_handle(41));
+    let mirror = MirrorObserver::default().set_on(&mut scene);
+    scene.remove(node4_key);
+    assert!(mirror.lock().unwrap().events == vec![MirrorEvent::Removed(node4_key, placement(root_key, None, None), vec![])]);
+}
@@ -0,0 +1,87 @@
+#![allow(clippy::assertions_on_constants)]
+
+use rsg::scene::*;
+use rsg::components::*;
+use nalgebra_glm as glm;
+
+struct TestObserver;
+
+impl RSGObserver for TestObserver {
+    fn notify(&mut self, _event: RSGEvent) {
+    }
+}
+
+type TestScene = RSGScene::<RSGComponentLinks, TestObserver>;
+
+fn transform_point(m: &glm::Mat4, p: glm::Vec3) -> glm::Vec3 {
+    let v = m * glm::vec4(p.x, p.y, p.z, 1.0);
+    glm::vec3(v.x, v.y, v.z)
+}
+
+#[test]
+fn relative_transform_between_nodes() {
+    let mut scene = TestScene::new();
+    let mut components = RSGComponentContainer::default();
+    let root_key = components.add_default_root(&mut scene);
+
+    // ROOT(A(B, NOTRANSFORM(C)))
+    let a_key = scene.append(root_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components).transform(glm::translation(&glm::vec3(10.0, 0.0, 0.0))).links()));
+    let b_key = scene.append(a_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components).transform(glm::translation(&glm::vec3(0.0, 5.0, 0.0))).links()));
+    let no_transform_key = scene.append(a_key, RSGNode::new());
+    let c_key = scene.append(no_transform_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components).transform(glm::translation(&glm::vec3(0.0, 0.0, -2.0))).links()));
+
+    // the origin of B is at (0, 5, -(-2)) relative to C
+    let m = relative_transform(&components.transforms, &scene, b_key, c_key).unwrap();
+    assert!(glm::distance(&transform_point(&m, glm::zero()), &glm::vec3(0.0, 5.0, 2.0)) < 0.0001);
+
+    // and the other way around
+    let m_inv = relative_transform(&components.transforms, &scene, c_key, b_key).unwrap();
+    assert!(glm::distance(&transform_point(&m_inv, glm::vec3(0.0, 5.0, 2.0)), &glm::zero()) < 0.0001);
+
+    // to an ancestor, world space of the root
+    let m = relative_transform(&components.transforms, &scene, c_key, root_key).unwrap();
+    assert!(glm::distance(&transform_point(&m, glm::vec3(1.0, 1.0, 1.0)), &glm::vec3(11.0, 1.0, -1.0)) < 0.0001);
+    let m = relative_transform(&components.transforms, &scene, b_key, b_key).unwrap();
+    assert!(m == glm::one::<glm::Mat4>());
+
+    // not in the same tree
+    let detached = scene.detach(a_key);
+    assert!(relative_transform(&components.transforms, &scene, b_key, root_key).is_none());
+    scene.reattach(detached, root_key, RSGNodePosition::Append);
+}
+
+#[test]
+fn relative_transform_with_rotation() {
+    let mut scene = TestScene::new();
+    let mut components = RSGComponentContainer::default();
+    let root_key = components.add_default_root(&mut scene);
+
+    // ROOT(A(B, C)), A rotates by 90 degrees around Z, B and C are translated
+    let rotation = glm::rotation(std::f32::consts::FRAC_PI_2, &glm::vec3(0.0, 0.0, 1.0));
+    let b_translation = glm::translation(&glm::vec3(1.0, 0.0, 0.0));
+    let c_translation = glm::translation(&glm::vec3(0.0, 0.0, 3.0));
+    let a_key = scene.append(root_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components).transform(rotation).links()));
+    let b_key = scene.append(a_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components).transform(b_translation).links()));
+    let c_key = scene.append(a_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components).transform(c_translation).links()));
+
+    // world = local * parent_world, the same as in prepare_scene
+    let b_world = b_translation * rotation;
+    let m = relative_transform(&components.transforms, &scene, b_key, root_key).unwrap();
+    assert!(glm::distance(&transform_point(&m, glm::vec3(0.0, 1.0, 0.0)), &transform_point(&b_world, glm::vec3(0.0, 1.0, 0.0))) < 0.0001);
+    // the other order would give (-1, 1, 0)
+    assert!(glm::distance(&transform_point(&m, glm::vec3(0.0, 1.0, 0.0)), &glm::vec3(0.0, 0.0, 0.0)) < 0.0001);
+
+    // between siblings the rotation of the common ancestor takes part as well
+    let c_world = c_translation * rotation;
+    let m = relative_transform(&components.transforms, &scene, b_key, c_key).unwrap();
+    let expected = glm::inverse(&c_world) * b_world;
+    let p = glm::vec3(2.0, -1.0, 0.5);
+    assert!(glm::distance(&transform_point(&m, p), &transform_point(&expected, p)) < 0.0001);
+    assert!(glm::distance(&transform_point(&m, glm::vec3(0.0, 1.0, 0.0)), &glm::vec3(0.0, 0.0, -3.0)) < 0.0001);
+}
@@ -0,0 +1,178 @@
+#![allow(clippy::assertions_on_constants)]
+
+use rsg::scene::*;
+use rsg::components::*;
+use rsg::material::*;
+use rsg::mesh::*;
+use rsg::undo::*;
+use nalgebra_glm as glm;
+
+struct TestObserver {
+    events: Vec<RSGEvent>
+}
+
+impl RSGObserver for TestObserver {
+    fn notify(&mut self, event: RSGEvent) {
+        self.events.push(event);
+    }
+}
+
+impl TestObserver {
+    fn new() -> Self {
+        TestObserver {
+            events: vec![]
+        }
+    }
+}
+
+type TestScene = RSGScene::<RSGComponentLinks, TestObserver>;
+
+fn make_mesh(size: f32) -> RSGMesh {
+    RSGMesh {
+        vertex_views: smallvec::smallvec![],
+        submeshes: smallvec::smallvec![],
+        bounds: RSGAabb {
+            minimum: glm::vec3(-size, -size, 0.0),
+            maximum: glm::vec3(size, size, 0.0)
+        }
+    }
+}
+
+fn make_node(components: &mut RSGComponentContainer) -> RSGNode<RSGComponentLinks> {
+    RSGNode::with_component_links(
+        RSGComponentBuilder::new(components).transform(glm::one()).opacity(1.0).links())
+}
+
+fn take_events(scene: &mut TestScene) -> Vec<RSGEvent> {
+    let obs = scene.take_observer().unwrap();
+    scene.set_observer(TestObserver::new());
+    obs.events
+}
+
+#[test]
+fn undo_redo_hierarchy_changes() {
+    let mut scene = TestScene::new();
+    let mut components = RSGComponentContainer::default();
+    let root_key = components.add_default_root(&mut scene);
+    let mut undo_stack = RSGUndoStack::new();
+    scene.set_observer(TestObserver::new());
+
+    // ROOT(NODE1, NODE2)
+    let node = make_node(&mut components);
+    let node1_key = undo_stack.insert(&mut components, &mut scene, root_key, node, RSGNodePosition::Append);
+    let node = make_node(&mut components);
+    let node2_key = undo_stack.insert(&mut components, &mut scene, root_key, node, RSGNodePosition::Append);
+    // ROOT(NODE2(NODE1))
+    undo_stack.move_subtree(&mut components, &mut scene, node1_key, node2_key, RSGNodePosition::Append);
+    // ROOT
+    undo_stack.remove(&mut components, &mut scene, node2_key);
+    assert!(scene.children(root_key).count() == 0);
+    // the removed subtree is only unlinked, keeping its keys and components for undo
+    assert!(scene.node_count() == 3 && !scene.is_reachable(node2_key) && !scene.is_reachable(node1_key));
+    assert!(take_events(&mut scene).last() == Some(&RSGEvent::SubtreeAboutToBeTemporarilyDetached(node2_key)));
+
+    assert!(undo_stack.undo_name() == Some("Remove"));
+    assert!(undo_stack.undo(&mut components, &mut scene));
+    assert!(take_events(&mut scene) == vec![RSGEvent::SubtreeAddedOrReattached(node2_key)]);
+    assert!(scene.is_reachable(node1_key) && scene[node1_key].parent_key == Some(node2_key));
+
+    assert!(undo_stack.undo(&mut components, &mut scene));
+    assert!(take_events(&mut scene) == vec![RSGEvent::SubtreeAboutToBeTemporarilyDetached(node1_key),
+        RSGEvent::SubtreeAddedOrReattached(node1_key)]);
+    let children: Vec<RSGNodeKey> = scene.children(root_key).collect();
+    assert!(children == vec![node1_key, node2_key]);
+
+    assert!(undo_stack.undo(&mut components, &mut scene));
+    assert!(undo_stack.undo(&mut components, &mut scene));
+    assert!(!undo_stack.undo(&mut components, &mut scene));
+    assert!(take_events(&mut scene) == vec![RSGEvent::SubtreeAboutToBeTemporarilyDetached(node2_key),
+        RSGEvent::SubtreeAboutToBeTemporarilyDetached(node1_key)]);
+    assert!(scene.children(root_key).count() == 0);
+
+    // redo everything
+    while undo_stack.redo(&mut components, &mut scene) {}
+    assert!(take_events(&mut scene).last() == Some(&RSGEvent::SubtreeAboutToBeTemporarilyDetached(node2_key)));
+    assert!(!undo_stack.can_redo() && undo_stack.undo_name() == Some("Remove"));
+    assert!(scene.children(root_key).count() == 0);
+    assert!(scene[node1_key].parent_key == Some(node2_key));
+    assert!(components.transforms.len() == 3);
+
+    // dropping the history destroys the removed subtree
+    undo_stack.clear(&mut components, &mut scene);
+    assert!(!scene.is_valid(node1_key) && !scene.is_valid(node2_key));
+    assert!(scene.node_count() == 1);
+    assert!(components.transforms.len() == 1 && components.opacities.len() == 1);
+}
+
+#[test]
+fn undo_redo_component_values() {
+    let mut scene = TestScene::new();
+    let mut components = RSGComponentContainer::default();
+    let root_key = components.add_default_root(&mut scene);
+    let mut undo_stack = RSGUndoStack::new();
+
+    let mut material = RSGMaterial {
+        shader_set_id: 1,
+        property_values: Default::default(),
+        graphics_state: Default::default()
+    };
+    let red = RSGMaterialPropertyValue::Custom(RSGMaterialCustomValue::Vec3(glm::vec3(1.0, 0.0, 0.0)));
+    let green = RSGMaterialPropertyValue::Custom(RSGMaterialCustomValue::Vec3(glm::vec3(0.0, 1.0, 0.0)));
+    material.property_values.insert("color".to_owned(), red);
+    let node_key = scene.append(root_key, RSGNode::with_component_links(
+        RSGComponentBuilder::new(&mut components)
+        .transform(glm::one())
+        .opacity(1.0)
+        .material(material)
+        .mesh(make_mesh(1.0))
+        .links()));
+    let links = *scene.get_component_links(node_key);
+    scene.set_observer(TestObserver::new());
+
+    undo_stack.begin_step("Edit");
+    undo_stack.set_local_transform(&mut components, &mut scene, node_key, glm::translation(&glm::vec3(1.0, 2.0, 3.0)));
+    undo_stack.set_opacity(&mut components, &mut scene, node_key, 0.5);
+    undo_stack.set_material_property_value(&mut components, &mut scene, node_key, "color", Some(green));
+    undo_stack.set_mesh(&mut components, &mut scene, node_key, make_mesh(2.0));
+    undo_stack.end_step(&mut components, &mut scene);
+    assert!(undo_stack.undo_name() == Some("Edit"));
+    take_events(&mut scene);
+
+    // the whole step is undone at once
+    assert!(undo_stack.undo(&mut components, &mut scene));
+    assert!(!undo_stack.can_undo() && undo_stack.redo_name() == Some("Edit"));
+    assert!(components.transforms[links.transform_key.unwrap()].local_transform == glm::one::<glm::Mat4>());
+    assert!(components.opacities[links.opacity_key.unwrap()].opacity == 1.0);
+    assert!(components.material_data[links.material_key.unwrap()].property_values["color"] == red);
+    assert!(components.mesh_data[links.mesh_key.unwrap()] == make_mesh(1.0));
+    assert!(take_events(&mut scene) == vec![
+        RSGEvent::Dirty(node_key, RSGDirtyFlags::MESH),
+        RSGEvent::Dirty(node_key, RSGDirtyFlags::MATERIAL_VALUES),
+        RSGEvent::Dirty(node_key, RSGDirtyFlags::OPACITY),
+        RSGEvent::Dirty(node_key, RSGDirtyFlags::TRANSFORM)]);
+
+    assert!(undo_stack.redo(&mut components, &mut scene));
+    assert!(components.transforms[links.transform_key.unwrap()].local_transform == glm::translation(&glm::vec3(1.0, 2.0, 3.0)));
+    assert!(components.opacities[links.opacity_key.unwrap()].opacity == 0.5);
+    assert!(components.material_data[links.material_key.unwrap()].property_values["color"] == green);
+    assert!(components.mesh_data[links.mesh_key.unwrap()] == make_mesh(2.0));
+}
+
+#[test]
+fn new_step_discards_redo_history() {
+    let mut scene = TestScene::new();
+    let mut components = RSGComponentContainer::default();
+    let root_key = components.add_default_root(&mut scene);
+    let mut undo_stack = RSGUndoStack::new();
+
+    let node = make_node(&mut components);
+    let node_key = undo_stack.insert(&mut components, &mut scene, root_key, node, RSGNodePosition::Append);
+    assert!(undo_stack.undo(&mut components, &mut scene));
+    assert!(scene.node_count() == 2 && components.transforms.len() == 2);
+
+    // the undone add can no longer be redone, so the node goes away
+    undo_stack.set_opacity(&mut components, &mut scene, root_key, 0.5);
+    assert!(!undo_stack.can_redo() && undo_stack.undo_name() == Some("Opacity"));
+    assert!(scene.node_count() == 1 && !scene.is_valid(node_key));
+    assert!(components.transforms.len() == 1 && components.opacities.len() == 1);
+}